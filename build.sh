@@ -2,5 +2,5 @@
 set -e
 
 RUSTFLAGS='-C link-arg=-s' cargo build --target wasm32-unknown-unknown --release
-mkdir -p ../out
-cp target/wasm32-unknown-unknown/release/farming.wasm ../out/farming.wasm
\ No newline at end of file
+mkdir -p res
+cp target/wasm32-unknown-unknown/release/farming.wasm res/farming.wasm
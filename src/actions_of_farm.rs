@@ -1,11 +1,16 @@
-use crate::farm::{ContractNFTTokenId, Farm, FarmId, FarmInfo, Status, TermsJson};
-use crate::seed::Seed;
-use crate::utils::{ext_nft, ext_self, parse_farm_id, XCC_GAS, GAS_FOR_NFT_TRANSFER};
+use crate::farm::{ContractNFTTokenId, Farm, FarmId, FarmIdJson, FarmInfo, FarmKind, FarmStats, Status, TermsJson};
+use crate::errors::{require, FarmingError};
+use crate::events::Event;
+use crate::utils::{
+    contract_token_id, ext_ft, ext_mt, ext_nft, ext_self, parse_contract_token_id,
+    NftToken, ValidFarmId, XCC_GAS, GAS_FOR_FT_TRANSFER, GAS_FOR_MT_TRANSFER, GAS_FOR_NFT_VIEW,
+    GAS_FOR_NFT_TRANSFER, GAS_FOR_RESOLVE_UNSTAKE, GAS_FOR_VERIFY_CALLBACK, MAX_VERIFY_NFTS,
+};
 use crate::*;
 use near_sdk::json_types::{U128, U64};
-use near_sdk::{assert_one_yocto, env, near_bindgen, Balance, Promise, PromiseResult};
+use near_sdk::{assert_one_yocto, env, near_bindgen, Balance, Promise, PromiseOrValue, PromiseResult};
 use near_sdk::{serde_json, Timestamp};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 #[near_bindgen]
 impl Contract {
@@ -15,15 +20,25 @@ impl Contract {
         terms: TermsJson,
         nft_contract_id: &AccountId,
         accepted_nfts: HashSet<NFTTokenId>,
-    ) -> Promise {
-        assert!(
+    ) -> PromiseOrValue<FarmIdJson> {
+        self.assert_running();
+        require(
             self.is_whitelist_nft_contract(nft_contract_id),
-            "We are not connected with this NFT contract"
+            FarmingError::NftContractNotWhitelisted,
         );
+        let config = self.internal_get_nft_contract(nft_contract_id);
+        require(config.can_add_farm(), FarmingError::TooManyFarms);
 
         let sender_id = env::predecessor_account_id();
-        ext_nft::is_creator_of_nfts(sender_id.clone(), accepted_nfts.clone(), nft_contract_id, 0, XCC_GAS).then(
-            ext_self::callback_check_nfts_owner(
+        match config.creator_mode {
+            CreatorMode::CreatorOfNfts => ext_nft::is_creator_of_nfts(
+                sender_id.clone(),
+                accepted_nfts.clone(),
+                nft_contract_id,
+                0,
+                XCC_GAS,
+            )
+            .then(ext_self::callback_check_nfts_owner(
                 sender_id,
                 terms,
                 nft_contract_id.into(),
@@ -31,28 +46,469 @@ impl Contract {
                 &env::current_account_id(),
                 0,
                 XCC_GAS,
+            ))
+            .into(),
+            CreatorMode::ContractOwner => ext_nft::owner_id(nft_contract_id, 0, GAS_FOR_NFT_VIEW)
+                .then(ext_self::callback_check_contract_owner(
+                    sender_id,
+                    terms,
+                    nft_contract_id.into(),
+                    accepted_nfts,
+                    &env::current_account_id(),
+                    0,
+                    XCC_GAS,
+                ))
+                .into(),
+            CreatorMode::Admins => {
+                require(
+                    self.is_nft_contract_admin(nft_contract_id, &sender_id),
+                    FarmingError::NotNftContractAdmin,
+                );
+                PromiseOrValue::Value(
+                    self.internal_add_farm(
+                        sender_id,
+                        terms,
+                        FarmKind::NonFungible,
+                        nft_contract_id.clone(),
+                        accepted_nfts,
+                    )
+                    .to_string(),
+                )
+            }
+            CreatorMode::Permissionless => PromiseOrValue::Value(
+                self.internal_add_farm(
+                    sender_id,
+                    terms,
+                    FarmKind::NonFungible,
+                    nft_contract_id.clone(),
+                    accepted_nfts,
+                )
+                .to_string(),
             ),
+        }
+    }
+
+    #[payable]
+    pub fn create_ft_farm(
+        &mut self,
+        terms: TermsJson,
+        ft_contract_id: ValidAccountId,
+        stake_unit: U128,
+    ) -> FarmIdJson {
+        self.assert_running();
+        require(stake_unit.0 != 0, FarmingError::InvalidStakeUnit);
+        let kind = FarmKind::Fungible {
+            ft_contract_id: ft_contract_id.into(),
+            stake_unit: stake_unit.into(),
+        };
+        self.internal_add_farm(
+            env::predecessor_account_id(),
+            terms,
+            kind,
+            String::new(),
+            HashSet::new(),
         )
+        .to_string()
     }
 
+    /// FT farm where staking a NFT of a whitelisted contract boosts the yield,
+    /// see `set_nft_boost`.
     #[payable]
-    pub fn claim_reward_by_farm(&mut self, farm_id: FarmId) {
+    pub fn create_hybrid_farm(
+        &mut self,
+        terms: TermsJson,
+        ft_contract_id: ValidAccountId,
+        stake_unit: U128,
+    ) -> FarmIdJson {
+        self.assert_running();
+        require(stake_unit.0 != 0, FarmingError::InvalidStakeUnit);
+        let kind = FarmKind::Hybrid {
+            ft_contract_id: ft_contract_id.into(),
+            stake_unit: stake_unit.into(),
+        };
+        self.internal_add_farm(
+            env::predecessor_account_id(),
+            terms,
+            kind,
+            String::new(),
+            HashSet::new(),
+        )
+        .to_string()
+    }
+
+    /// Farm staking NEP-245 `accepted_tokens` of `mt_contract_id`.
+    #[payable]
+    pub fn create_mt_farm(
+        &mut self,
+        terms: TermsJson,
+        mt_contract_id: ValidAccountId,
+        accepted_tokens: HashSet<NFTTokenId>,
+    ) -> FarmIdJson {
+        self.assert_running();
+        require(!accepted_tokens.is_empty(), FarmingError::NoAcceptedTokens);
+        let kind = FarmKind::MultiToken {
+            mt_contract_id: mt_contract_id.into(),
+        };
+        self.internal_add_farm(
+            env::predecessor_account_id(),
+            terms,
+            kind,
+            String::new(),
+            accepted_tokens,
+        )
+        .to_string()
+    }
+
+    #[payable]
+    pub fn claim_reward_by_farm(&mut self, farm_id: FarmIdJson) {
+        self.assert_running();
+        let farm_id = self.internal_unwrap_farm_id(&farm_id);
         let sender_id = env::predecessor_account_id();
         let new_staked_at = env::block_timestamp();
-        let farm = self.farms.get(&farm_id).unwrap();
+        let farm = self.internal_unwrap_farm(&farm_id);
         let will_claim_amount = self.internal_get_claimable(&farm_id, &sender_id, new_staked_at);
 
-        assert!(farm.status == Status::Running, "Farm is not running");
-        assert!(farm.terms.start_at <= new_staked_at, "Farm is not started");
-        assert_ne!(will_claim_amount, 0, "Not time for claiming reward");
+        require(farm.status == Status::Running, FarmingError::FarmNotRunning);
+        require(farm.terms.start_at <= new_staked_at, FarmingError::FarmNotStarted);
+        require(will_claim_amount != 0, FarmingError::NothingToClaim);
 
-        self.internal_claim_reward_by_farm(&farm_id, &sender_id, will_claim_amount, new_staked_at);
+        let approved_nfts = self.internal_approved_nfts(&farm_id, &sender_id);
+        if approved_nfts.is_empty() {
+            self.internal_claim_reward_by_farm(&farm_id, &sender_id, will_claim_amount, new_staked_at);
+        } else {
+            self.internal_verify_approved_nfts(farm_id, sender_id, approved_nfts, None);
+        }
     }
 
+    /// Also works while paused, the reward then stays pending.
     #[payable]
-    pub fn withdraw(&mut self, farm_id: FarmId, token_id: NFTTokenId) {
+    pub fn withdraw(&mut self, farm_id: FarmIdJson, token_id: NFTTokenId) {
         assert_one_yocto();
-        self.internal_withdraw(farm_id, token_id);
+        let farm_id = self.internal_unwrap_farm_id(&farm_id);
+        let sender_id = env::predecessor_account_id();
+        let farm = self.internal_unwrap_farm(&farm_id);
+        let is_approved = farm
+            .staked_nfts
+            .get(&token_id)
+            .map_or(false, |token| token.approval_id.is_some());
+
+        if is_approved {
+            let approved_nfts = self.internal_approved_nfts(&farm_id, &sender_id);
+            require(approved_nfts.contains(&token_id), FarmingError::NotNftOwner);
+            self.internal_verify_approved_nfts(farm_id, sender_id, approved_nfts, Some(token_id));
+        } else {
+            self.internal_withdraw(sender_id, farm_id, token_id);
+        }
+    }
+
+    /// Unstakes the NFTs of `token_ids` staked by approval which moved or
+    /// lost the approval, their owners forfeit the reward of this farm.
+    /// Anyone can call it with up to `MAX_VERIFY_NFTS` NFTs at a time.
+    pub fn verify_approved_nfts(&mut self, farm_id: FarmIdJson, token_ids: Vec<NFTTokenId>) -> Promise {
+        let farm_id = self.internal_unwrap_farm_id(&farm_id);
+        let farm = self.internal_unwrap_farm(&farm_id);
+        let token_ids: Vec<NFTTokenId> = token_ids
+            .into_iter()
+            .filter(|token_id| {
+                farm.staked_nfts
+                    .get(token_id)
+                    .map_or(false, |token| token.approval_id.is_some())
+            })
+            .collect();
+        require(!token_ids.is_empty(), FarmingError::NftNotFound);
+        require(token_ids.len() <= MAX_VERIFY_NFTS, FarmingError::TooManyNfts);
+
+        let mut promise = ext_nft::nft_token(token_ids[0].clone(), &farm.nft_contract_id, 0, GAS_FOR_NFT_VIEW);
+        for token_id in token_ids.iter().skip(1) {
+            promise = promise.and(ext_nft::nft_token(
+                token_id.clone(),
+                &farm.nft_contract_id,
+                0,
+                GAS_FOR_NFT_VIEW,
+            ));
+        }
+        promise.then(ext_self::callback_evict_approved_nfts(
+            farm_id,
+            token_ids,
+            &env::current_account_id(),
+            0,
+            GAS_FOR_VERIFY_CALLBACK,
+        ))
+    }
+
+    /// Gives back the stake even while the contract is paused, the farmer
+    /// gives up all unpaid reward in this farm. NFTs are given back by
+    /// `token_ids`, staked FT and multi token balances in full. No reward
+    /// is computed, so a farm whose reward can not be settled still lets
+    /// its stake go.
+    #[payable]
+    pub fn emergency_withdraw(&mut self, farm_id: FarmIdJson, token_ids: Vec<NFTTokenId>) {
+        assert_one_yocto();
+        let farm_id = self.internal_unwrap_farm_id(&farm_id);
+        let sender_id = env::predecessor_account_id();
+        let new_staked_at = env::block_timestamp();
+        let mut farmer = self.internal_unwrap_farmer(&sender_id);
+        let mut farm = self.internal_unwrap_farm(&farm_id);
+        let mut staking_info = farmer.staking.get(&farm_id).unwrap_or_else(|| FarmingError::NotStaked.panic());
+        farm.stream_weight = farm.stream_weight.saturating_sub(farm.staking_weight(&staking_info));
+
+        for token_id in token_ids {
+            let token = farm
+                .staked_nfts
+                .get(&token_id)
+                .unwrap_or_else(|| FarmingError::NftNotFound.panic());
+            require(token.owner_id == sender_id, FarmingError::NotNftOwner);
+            farm.staked_nfts.remove(&token_id);
+            if farm.is_hybrid() {
+                staking_info.boost_bps = 0;
+                let (nft_contract_id, token_id) = parse_contract_token_id(&token_id);
+                self.internal_withdraw_nft(token_id, &sender_id, nft_contract_id);
+            } else {
+                staking_info.amount -= 1;
+                staking_info.token_balances.remove(&token_id);
+                if token.approval_id.is_some() {
+                    self.approved_nfts
+                        .remove(&contract_token_id(&farm.nft_contract_id, &token_id));
+                } else {
+                    self.internal_withdraw_nft(token_id, &sender_id, farm.nft_contract_id.clone());
+                }
+            }
+        }
+        let ft_amount = match farm.kind {
+            FarmKind::Fungible { .. } | FarmKind::Hybrid { .. } => staking_info.amount,
+            _ => 0,
+        };
+        let mt_balances: Vec<(NFTTokenId, Balance)> = match farm.kind {
+            FarmKind::MultiToken { .. } => staking_info.token_balances.drain().collect(),
+            _ => vec![],
+        };
+        if ft_amount != 0 || !mt_balances.is_empty() {
+            farm.total_staked = farm.total_staked.saturating_sub(staking_info.amount);
+            staking_info.amount = 0;
+        }
+        if staking_info.token_balances.is_empty() {
+            staking_info.completed_sets.clear();
+            staking_info.set_bonus_bps = 0;
+            staking_info.sets_version = farm.sets_version;
+        } else {
+            farm.update_completed_sets(&mut staking_info);
+        }
+        // what is left of the stake earns from now on
+        staking_info.last_staked_at = new_staked_at;
+        staking_info.reward_per_weight_paid = farm.reward_per_weight;
+        farm.add_stream_weight(&staking_info);
+        staking_info.pending_reward = 0;
+        staking_info.pending_stream_reward = 0;
+        farmer.staking.insert(&farm_id, &staking_info);
+        self.farmers.insert(&sender_id, &farmer);
+        self.farms.insert(&farm_id, &farm);
+
+        if ft_amount != 0 {
+            self.internal_withdraw_ft(ft_amount, &sender_id, farm.ft_contract_id().cloned().unwrap());
+        }
+        if !mt_balances.is_empty() {
+            let (token_ids, amounts): (Vec<NFTTokenId>, Vec<U128>) = mt_balances
+                .into_iter()
+                .map(|(token_id, balance)| (token_id, U128(balance)))
+                .unzip();
+            self.internal_withdraw_mt(token_ids, amounts, &sender_id, farm.mt_contract_id().cloned().unwrap());
+        }
+        env::log(
+            format!(
+                "Emergency withdraw of {} in farm {}, reward is forfeited",
+                sender_id, farm_id
+            )
+            .as_bytes(),
+        );
+    }
+
+    /// Also works while paused, the reward then stays pending.
+    #[payable]
+    pub fn unstake_ft(&mut self, farm_id: FarmIdJson, amount: U128) -> Promise {
+        assert_one_yocto();
+        let farm_id = self.internal_unwrap_farm_id(&farm_id);
+        self.internal_unstake_ft(farm_id, amount.into())
+    }
+
+    /// Also works while paused, the reward then stays pending.
+    #[payable]
+    pub fn unstake_mt(
+        &mut self,
+        farm_id: FarmIdJson,
+        token_ids: Vec<NFTTokenId>,
+        amounts: Vec<U128>,
+    ) -> Promise {
+        assert_one_yocto();
+        let farm_id = self.internal_unwrap_farm_id(&farm_id);
+        self.internal_unstake_mt(farm_id, token_ids, amounts)
+    }
+
+    /// Adds NFTs to a farm, checked like at `create_farm`.
+    /// Attached deposit pays the storage, the rest is refunded.
+    #[payable]
+    pub fn add_accepted_nfts(&mut self, farm_id: FarmIdJson, tokens: HashSet<NFTTokenId>) -> PromiseOrValue<()> {
+        self.assert_running();
+        let farm_id = self.internal_unwrap_farm_id(&farm_id);
+        let farm = self.internal_unwrap_farm(&farm_id);
+        require(
+            env::predecessor_account_id() == farm.owner_id,
+            FarmingError::NotFarmOwner,
+        );
+        require(farm.status != Status::Ended, FarmingError::FarmEnded);
+
+        match farm.kind {
+            FarmKind::NonFungible => {}
+            FarmKind::MultiToken { .. } => {
+                self.internal_add_accepted_nfts(farm_id, tokens, env::attached_deposit())
+                    .unwrap_or_else(|error| error.panic());
+                return PromiseOrValue::Value(());
+            }
+            _ => FarmingError::WrongFarmKind.panic(),
+        }
+
+        // the deposit stays here until the callback uses or refunds it
+        match self.get_creator_mode(farm.nft_contract_id.clone()) {
+            CreatorMode::CreatorOfNfts => ext_nft::is_creator_of_nfts(
+                farm.owner_id.clone(),
+                tokens.clone(),
+                &farm.nft_contract_id,
+                0,
+                XCC_GAS,
+            )
+            .then(ext_self::callback_add_accepted_nfts(
+                farm_id,
+                tokens,
+                CreatorMode::CreatorOfNfts,
+                U128(env::attached_deposit()),
+                &env::current_account_id(),
+                0,
+                XCC_GAS,
+            ))
+            .into(),
+            CreatorMode::ContractOwner => ext_nft::owner_id(&farm.nft_contract_id, 0, GAS_FOR_NFT_VIEW)
+                .then(ext_self::callback_add_accepted_nfts(
+                    farm_id,
+                    tokens,
+                    CreatorMode::ContractOwner,
+                    U128(env::attached_deposit()),
+                    &env::current_account_id(),
+                    0,
+                    XCC_GAS,
+                ))
+                .into(),
+            CreatorMode::Admins => {
+                require(
+                    self.is_nft_contract_admin(&farm.nft_contract_id, &farm.owner_id),
+                    FarmingError::NotNftContractAdmin,
+                );
+                self.internal_add_accepted_nfts(farm_id, tokens, env::attached_deposit())
+                    .unwrap_or_else(|error| error.panic());
+                PromiseOrValue::Value(())
+            }
+            CreatorMode::Permissionless => {
+                self.internal_add_accepted_nfts(farm_id, tokens, env::attached_deposit())
+                    .unwrap_or_else(|error| error.panic());
+                PromiseOrValue::Value(())
+            }
+        }
+    }
+
+    /// Changes `reward_per_session` from `effective_at` (now if 0),
+    /// reward accrued before keeps the previous rate.
+    pub fn update_reward_rate(&mut self, farm_id: FarmIdJson, new_rate: U128, effective_at: U64) {
+        let farm_id = self.internal_unwrap_farm_id(&farm_id);
+        let mut farm = self.internal_unwrap_farm(&farm_id);
+        require(
+            env::predecessor_account_id() == farm.owner_id,
+            FarmingError::NotFarmOwner,
+        );
+        require(farm.status != Status::Ended, FarmingError::FarmEnded);
+        require(farm.closing_at.is_none(), FarmingError::FarmClosing);
+
+        let now = env::block_timestamp();
+        let effective_at = if effective_at.0 == 0 { now } else { effective_at.0 };
+        require(effective_at >= now, FarmingError::RateInPast);
+        if let Some((last_effective_at, _)) = farm.terms.rate_history.last() {
+            require(effective_at > *last_effective_at, FarmingError::RateOutOfOrder);
+        }
+        farm.terms.rate_history.push((effective_at, new_rate.into()));
+        self.farms.insert(&farm_id, &farm);
+        env::log(
+            format!(
+                "Reward rate of farm {} changes to {} at {}",
+                farm_id, new_rate.0, effective_at
+            )
+            .as_bytes(),
+        );
+    }
+
+    /// Sets the bonuses of NFTs staked without interruption from now, tiers
+    /// sorted by days with growing bonuses. Withdrawing a NFT resets its bonus.
+    pub fn set_loyalty_curve(&mut self, farm_id: FarmIdJson, loyalty_curve: Vec<LoyaltyTier>) {
+        let farm_id = self.internal_unwrap_farm_id(&farm_id);
+        let mut farm = self.internal_unwrap_farm(&farm_id);
+        require(
+            env::predecessor_account_id() == farm.owner_id,
+            FarmingError::NotFarmOwner,
+        );
+        require(farm.kind == FarmKind::NonFungible, FarmingError::WrongFarmKind);
+        require(
+            loyalty_curve.len() <= MAX_LOYALTY_TIERS
+                && loyalty_curve.iter().all(|tier| tier.bonus_bps <= MAX_LOYALTY_BONUS_BPS)
+                && loyalty_curve
+                    .windows(2)
+                    .all(|tiers| tiers[0].days < tiers[1].days && tiers[0].bonus_bps <= tiers[1].bonus_bps),
+            FarmingError::InvalidLoyaltyCurve,
+        );
+        let replaced = std::mem::replace(&mut farm.terms.loyalty_curve, loyalty_curve);
+        farm.terms.loyalty_history.push((env::block_timestamp(), replaced));
+        self.farms.insert(&farm_id, &farm);
+    }
+
+    /// Sets the NFT sets earning a bonus from now, farmers get it
+    /// on their next stake, withdraw or claim.
+    pub fn set_nft_sets(&mut self, farm_id: FarmIdJson, nft_sets: Vec<NftSet>) {
+        let farm_id = self.internal_unwrap_farm_id(&farm_id);
+        let mut farm = self.internal_unwrap_farm(&farm_id);
+        require(
+            env::predecessor_account_id() == farm.owner_id,
+            FarmingError::NotFarmOwner,
+        );
+        require(farm.kind == FarmKind::NonFungible, FarmingError::WrongFarmKind);
+        let names: HashSet<&String> = nft_sets.iter().map(|nft_set| &nft_set.name).collect();
+        require(
+            nft_sets.len() <= MAX_NFT_SETS
+                && names.len() == nft_sets.len()
+                && nft_sets.iter().all(|nft_set| match &nft_set.rule {
+                    SetRule::AllWithPrefix(prefix) => !prefix.is_empty(),
+                    SetRule::OneOfEach(prefixes) => !prefixes.is_empty(),
+                }),
+            FarmingError::InvalidNftSets,
+        );
+        farm.terms.nft_sets = nft_sets;
+        farm.count_set_sizes();
+        farm.touch_sets(env::block_timestamp());
+        self.farms.insert(&farm_id, &farm);
+    }
+
+    /// Staked NFTs which are removed stay in the farm until withdrawn.
+    pub fn remove_accepted_nfts(&mut self, farm_id: FarmIdJson, tokens: HashSet<NFTTokenId>) {
+        let farm_id = self.internal_unwrap_farm_id(&farm_id);
+        let mut farm = self.internal_unwrap_farm(&farm_id);
+        require(
+            env::predecessor_account_id() == farm.owner_id,
+            FarmingError::NotFarmOwner,
+        );
+        require(farm.status != Status::Ended, FarmingError::FarmEnded);
+        require(
+            matches!(farm.kind, FarmKind::NonFungible | FarmKind::MultiToken { .. }),
+            FarmingError::WrongFarmKind,
+        );
+        for token_id in tokens.iter() {
+            farm.set_nft_accepted(token_id, false);
+        }
+        farm.touch_sets(env::block_timestamp());
+        self.farms.insert(&farm_id, &farm);
     }
 
     /// View methods.
@@ -60,16 +516,27 @@ impl Contract {
         U64(self.farms.len())
     }
 
-    pub fn get_claimable_amount(&self, farm_id: FarmId, farmer_id: AccountId) -> U128 {
+    /// `None` for an unknown farm or a farmer not staking in it.
+    pub fn get_claimable_amount(&self, farm_id: FarmIdJson, farmer_id: AccountId) -> Option<U128> {
+        let farm_id = self.internal_parse_farm_id(&farm_id)?;
+        self.farmers.get(&farmer_id)?.staking.get(&farm_id)?;
         let new_staked_at = env::block_timestamp();
-        U128(self.internal_get_claimable(&farm_id, &farmer_id, new_staked_at))
+        Some(U128(self.internal_get_claimable(&farm_id, &farmer_id, new_staked_at)))
     }
 
-    pub fn list_farms(&self, from_index: u64, limit: u64) -> Vec<(String, FarmInfo)> {
+    pub fn get_farm_stats(&self, farm_id: FarmIdJson) -> Option<FarmStats> {
+        let farm = self.internal_get_farm(&farm_id)?;
+        Some(farm.stats(env::block_timestamp()))
+    }
+
+    pub fn list_farms(&self, from_index: u64, limit: u64) -> Vec<(FarmIdJson, FarmInfo)> {
         let keys = self.farms.keys_as_vector();
 
         (from_index..std::cmp::min(from_index + limit, self.farms.len()))
-            .map(|index| (keys.get(index).unwrap(), (&self.farms.get(&keys.get(index).unwrap()).unwrap()).into()))
+            .map(|index| {
+                let farm_id = keys.get(index).unwrap();
+                (farm_id.to_string(), (&self.farms.get(&farm_id).unwrap()).into())
+            })
             .collect()
     }
 
@@ -80,20 +547,268 @@ impl Contract {
         terms: TermsJson,
         nft_contract_id: &AccountId,
         accepted_nfts: HashSet<NFTTokenId>,
-    ) -> FarmId {
-        assert_eq!(env::promise_results_count(), 1, "ERR_TOO_MANY_RESULTS");
+    ) -> FarmIdJson {
+        require(env::promise_results_count() == 1, FarmingError::TooManyResults);
         match env::promise_result(0) {
             PromiseResult::NotReady => unreachable!(),
             PromiseResult::Successful(val) => {
                 if let Ok(is_owner) = serde_json::from_slice::<bool>(&val) {
-                    assert!(is_owner, "ERR_NOT_NFTS_OWNERS");
-                    self.internal_add_farm(owner_id, terms, nft_contract_id, accepted_nfts)
+                    require(is_owner, FarmingError::NotNftsOwner);
+                    self.internal_add_farm(
+                        owner_id,
+                        terms,
+                        FarmKind::NonFungible,
+                        nft_contract_id.clone(),
+                        accepted_nfts,
+                    )
+                    .to_string()
+                } else {
+                    FarmingError::WrongValReceived.panic()
+                }
+            }
+            PromiseResult::Failed => FarmingError::CallFailed.panic(),
+        }
+    }
+
+    #[private]
+    pub fn callback_check_contract_owner(
+        &mut self,
+        owner_id: AccountId,
+        terms: TermsJson,
+        nft_contract_id: &AccountId,
+        accepted_nfts: HashSet<NFTTokenId>,
+    ) -> FarmIdJson {
+        require(env::promise_results_count() == 1, FarmingError::TooManyResults);
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(val) => {
+                if let Ok(contract_owner_id) = serde_json::from_slice::<AccountId>(&val) {
+                    require(contract_owner_id == owner_id, FarmingError::NotContractOwner);
+                    self.internal_add_farm(
+                        owner_id,
+                        terms,
+                        FarmKind::NonFungible,
+                        nft_contract_id.clone(),
+                        accepted_nfts,
+                    )
+                    .to_string()
                 } else {
-                    env::panic(b"ERR_WRONG_VAL_RECEIVED")
+                    FarmingError::WrongValReceived.panic()
+                }
+            }
+            PromiseResult::Failed => FarmingError::CallFailed.panic(),
+        }
+    }
+
+    /// Adds the NFTs once the farm owner is verified, on any failure the
+    /// deposit of `add_accepted_nfts` is refunded.
+    #[private]
+    pub fn callback_add_accepted_nfts(
+        &mut self,
+        farm_id: FarmId,
+        tokens: HashSet<NFTTokenId>,
+        creator_mode: CreatorMode,
+        deposit: U128,
+    ) {
+        require(env::promise_results_count() == 1, FarmingError::TooManyResults);
+        let farm = self.internal_unwrap_farm(&farm_id);
+        let result = match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(val) => {
+                let is_allowed = match creator_mode {
+                    CreatorMode::ContractOwner => serde_json::from_slice::<AccountId>(&val)
+                        .map(|contract_owner_id| contract_owner_id == farm.owner_id),
+                    _ => serde_json::from_slice::<bool>(&val),
+                };
+                match is_allowed {
+                    Ok(true) => self.internal_add_accepted_nfts(farm_id, tokens, deposit.0),
+                    Ok(false) => Err(FarmingError::NotNftsOwner),
+                    Err(_) => Err(FarmingError::WrongValReceived),
+                }
+            }
+            PromiseResult::Failed => Err(FarmingError::CallFailed),
+        };
+        if let Err(error) = result {
+            Event::AcceptedNftsRefunded {
+                farm_id: &farm_id.to_string(),
+                owner_id: &farm.owner_id,
+                deposit,
+                code: error.code(),
+                message: error.message(),
+            }
+            .emit();
+            if deposit.0 != 0 {
+                Promise::new(farm.owner_id).transfer(deposit.0);
+            }
+        }
+    }
+
+    /// Pays the farmer only if all NFTs staked by approval are still owned
+    /// and approved, otherwise they are unstaked and the reward is forfeited.
+    #[private]
+    pub fn callback_verify_approved_nfts(
+        &mut self,
+        farm_id: FarmId,
+        farmer_id: AccountId,
+        token_ids: Vec<NFTTokenId>,
+        withdraw_token_id: Option<NFTTokenId>,
+    ) {
+        require(
+            env::promise_results_count() == token_ids.len() as u64,
+            FarmingError::TooManyResults,
+        );
+        let farm = self.internal_unwrap_farm(&farm_id);
+        let current_account_id = env::current_account_id();
+        let mut moved_nfts: Vec<NFTTokenId> = vec![];
+        for (index, token_id) in token_ids.into_iter().enumerate() {
+            let approval_id = match farm.staked_nfts.get(&token_id) {
+                Some(token) => token.approval_id,
+                None => continue,
+            };
+            match env::promise_result(index as u64) {
+                PromiseResult::NotReady => unreachable!(),
+                PromiseResult::Successful(val) => {
+                    if let Ok(token) = serde_json::from_slice::<Option<NftToken>>(&val) {
+                        let is_approved = match (token, approval_id) {
+                            (Some(token), Some(approval_id)) => {
+                                token.is_approved(&farmer_id, &current_account_id, approval_id)
+                            }
+                            _ => false,
+                        };
+                        if !is_approved {
+                            moved_nfts.push(token_id);
+                        }
+                    } else {
+                        FarmingError::WrongValReceived.panic()
+                    }
+                }
+                PromiseResult::Failed => FarmingError::CallFailed.panic(),
+            }
+        }
+
+        let new_staked_at = env::block_timestamp();
+        if moved_nfts.is_empty() {
+            let will_claim_amount = self.internal_get_claimable(&farm_id, &farmer_id, new_staked_at);
+            if will_claim_amount != 0 {
+                self.internal_claim_reward_by_farm(&farm_id, &farmer_id, will_claim_amount, new_staked_at);
+            }
+        } else {
+            self.internal_forfeit_approved_nfts(&farm_id, &farmer_id, &moved_nfts, new_staked_at);
+        }
+
+        if let Some(token_id) = withdraw_token_id {
+            if !moved_nfts.contains(&token_id) {
+                self.internal_withdraw(farmer_id, farm_id, token_id);
+            }
+        }
+    }
+
+    /// Unstakes the NFTs which are not approved anymore by their owner,
+    /// a failed view keeps the NFT staked.
+    #[private]
+    pub fn callback_evict_approved_nfts(&mut self, farm_id: FarmId, token_ids: Vec<NFTTokenId>) {
+        require(
+            env::promise_results_count() == token_ids.len() as u64,
+            FarmingError::TooManyResults,
+        );
+        let farm = self.internal_unwrap_farm(&farm_id);
+        let current_account_id = env::current_account_id();
+        let mut moved_nfts: HashMap<AccountId, Vec<NFTTokenId>> = HashMap::new();
+        for (index, token_id) in token_ids.into_iter().enumerate() {
+            let (owner_id, approval_id) = match farm.staked_nfts.get(&token_id) {
+                Some(StakedInfo { owner_id, approval_id: Some(approval_id), .. }) => (owner_id, approval_id),
+                _ => continue,
+            };
+            if let PromiseResult::Successful(val) = env::promise_result(index as u64) {
+                let is_approved = match serde_json::from_slice::<Option<NftToken>>(&val) {
+                    Ok(Some(token)) => token.is_approved(&owner_id, &current_account_id, approval_id),
+                    Ok(None) => false,
+                    Err(_) => continue,
+                };
+                if !is_approved {
+                    moved_nfts.entry(owner_id).or_default().push(token_id);
                 }
             }
-            PromiseResult::Failed => env::panic(b"ERR_CALL_FAILED"),
         }
+
+        let new_staked_at = env::block_timestamp();
+        for (owner_id, token_ids) in moved_nfts {
+            self.internal_forfeit_approved_nfts(&farm_id, &owner_id, &token_ids, new_staked_at);
+        }
+    }
+
+    /// Puts the unstaked amount back into the stake when its transfer failed.
+    #[private]
+    pub fn callback_resolve_unstake_ft(&mut self, farm_id: FarmId, sender_id: AccountId, amount: U128) {
+        require(env::promise_results_count() == 1, FarmingError::TooManyResults);
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return;
+        }
+        let new_staked_at = env::block_timestamp();
+        let (mut farmer, mut farm) = match (self.farmers.get(&sender_id), self.farms.get(&farm_id)) {
+            (Some(farmer), Some(farm)) => (farmer, farm),
+            _ => {
+                env::log(
+                    format!("Unstake of {} in farm {} failed and can not be restored", sender_id, farm_id)
+                        .as_bytes(),
+                );
+                return;
+            }
+        };
+        let mut staking_info = farmer.staking.get(&farm_id).unwrap_or(StakingInfo::new(new_staked_at));
+        farm.checkpoint_reward(&mut staking_info, new_staked_at);
+        staking_info.amount += amount.0;
+        farm.total_staked += amount.0;
+        farm.add_stream_weight(&staking_info);
+        farmer.staking.insert(&farm_id, &staking_info);
+        self.farmers.insert(&sender_id, &farmer);
+        self.farms.insert(&farm_id, &farm);
+        env::log(
+            format!("Unstake of {} in farm {} failed, {} is staked again", sender_id, farm_id, amount.0)
+                .as_bytes(),
+        );
+    }
+
+    /// Puts the unstaked multi token balances back into the stake when
+    /// their transfer failed.
+    #[private]
+    pub fn callback_resolve_unstake_mt(
+        &mut self,
+        farm_id: FarmId,
+        sender_id: AccountId,
+        token_ids: Vec<NFTTokenId>,
+        amounts: Vec<U128>,
+    ) {
+        require(env::promise_results_count() == 1, FarmingError::TooManyResults);
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return;
+        }
+        let new_staked_at = env::block_timestamp();
+        let (mut farmer, mut farm) = match (self.farmers.get(&sender_id), self.farms.get(&farm_id)) {
+            (Some(farmer), Some(farm)) => (farmer, farm),
+            _ => {
+                env::log(
+                    format!("Unstake of {} in farm {} failed and can not be restored", sender_id, farm_id)
+                        .as_bytes(),
+                );
+                return;
+            }
+        };
+        let mut staking_info = farmer.staking.get(&farm_id).unwrap_or(StakingInfo::new(new_staked_at));
+        farm.checkpoint_reward(&mut staking_info, new_staked_at);
+        for (token_id, amount) in token_ids.iter().zip(amounts.iter()) {
+            *staking_info.token_balances.entry(token_id.clone()).or_insert(0) += amount.0;
+            staking_info.amount += amount.0;
+            farm.total_staked += amount.0;
+        }
+        farm.add_stream_weight(&staking_info);
+        farmer.staking.insert(&farm_id, &staking_info);
+        self.farmers.insert(&sender_id, &farmer);
+        self.farms.insert(&farm_id, &farm);
+        env::log(
+            format!("Unstake of {} in farm {} failed, the tokens are staked again", sender_id, farm_id)
+                .as_bytes(),
+        );
     }
 }
 
@@ -105,69 +820,229 @@ impl Contract {
         amount: Balance,
         new_staked_at: Timestamp,
     ) {
-        let (seed_id, _) = parse_farm_id(&farm_id);
+        // stake can leave while paused, its reward stays pending
+        if self.state != RunningState::Running {
+            return;
+        }
+        let seed_id = self.internal_unwrap_farm(farm_id).terms.seed_id;
         if let Some(_seed) = self.seeds.get(&seed_id) {
-            let mut farmer = self.farmers.get(&sender_id).unwrap();
-            let mut farm = self.farms.get(&farm_id).unwrap();
-            let remain_amount = farm.amount_of_reward;
+            let paid_amount = self.internal_settle_reward(farm_id, sender_id, amount, new_staked_at);
+            if paid_amount != 0 {
+                self.internal_claim_user_reward(paid_amount, sender_id, &seed_id, Some(*farm_id));
+            }
+        }
+    }
 
-            if remain_amount <= amount {
-                self.internal_claim_user_reward(remain_amount, sender_id, &seed_id);
-                farm.set_ended(remain_amount);
-            } else {
-                self.internal_claim_user_reward(amount, sender_id, &seed_id);
-                farm.amount_of_claimed += amount;
-                farm.amount_of_reward -= amount;
-                let mut staking_info = farmer.staking.get(&farm_id).unwrap();
-                staking_info.last_staked_at = new_staked_at;
-                farmer.staking.insert(&farm_id, &staking_info);
+    /// Takes up to `amount` of reward out of the farm for the farmer and
+    /// returns the part to pay now. The vesting share of it is added to
+    /// the vesting schedule of the farmer, reward accrued but not in
+    /// `amount` stays in `pending_reward` and `pending_stream_reward`.
+    pub(crate) fn internal_settle_reward(
+        &mut self,
+        farm_id: &FarmId,
+        sender_id: &AccountId,
+        amount: Balance,
+        new_staked_at: Timestamp,
+    ) -> Balance {
+        let mut farmer = self.internal_unwrap_farmer(&sender_id);
+        let mut farm = self.internal_unwrap_farm(&farm_id);
+        let mut staking_info = farmer.staking.get(&farm_id).unwrap();
+        farm.checkpoint_reward(&mut staking_info, new_staked_at);
+        farm.add_stream_weight(&staking_info);
+
+        let was_running = farm.status == Status::Running;
+        let paid_amount = farm.take_reward(&mut staking_info, amount);
+        let ended = was_running && farm.status == Status::Ended;
+        farmer.staking.insert(&farm_id, &staking_info);
+
+        let mut vested_amount = 0;
+        if let Some(vesting) = &farm.terms.vesting {
+            vested_amount = paid_amount * vesting.share_bps as u128 / BP_DENOMINATOR;
+            if vested_amount != 0 {
+                let mut schedule = farmer
+                    .vesting
+                    .get(&farm.terms.seed_id)
+                    .unwrap_or(VestingSchedule::new(new_staked_at));
+                let end_at = new_staked_at + (vesting.days as u128 * NANOS_PER_DAY) as Timestamp;
+                schedule.add(vested_amount, new_staked_at, end_at);
+                farmer.vesting.insert(&farm.terms.seed_id, &schedule);
             }
+        }
 
-            self.farmers.insert(&sender_id, &farmer);
-            self.farms.insert(&farm_id, &farm);
+        self.farmers.insert(&sender_id, &farmer);
+        self.farms.insert(&farm_id, &farm);
+        // the vested share counts as claimed once `claim_vested` pays it
+        self.internal_add_seed_claim(&farm.terms.seed_id, paid_amount - vested_amount, ended);
+        paid_amount - vested_amount
+    }
+
+    /// Pays the reward settled when a stake changes. Farmers having NFTs staked
+    /// by approval are paid only after `callback_verify_approved_nfts`,
+    /// until then the reward is kept pending by `checkpoint_reward`.
+    fn internal_auto_claim(
+        &mut self,
+        farm_id: &FarmId,
+        farmer_id: &AccountId,
+        amount: Balance,
+        new_staked_at: Timestamp,
+    ) {
+        if self.internal_approved_nfts(farm_id, farmer_id).is_empty() {
+            self.internal_claim_reward_by_farm(farm_id, farmer_id, amount, new_staked_at);
         }
     }
 
+    pub fn internal_approved_nfts(&self, farm_id: &FarmId, farmer_id: &AccountId) -> Vec<NFTTokenId> {
+        let farm = self.internal_unwrap_farm(farm_id);
+        self.farmers
+            .get(farmer_id)
+            .and_then(|farmer| farmer.staking.get(farm_id))
+            .map(|staking_info| farm.approved_nfts_of(&staking_info))
+            .unwrap_or_default()
+    }
+
+    fn internal_verify_approved_nfts(
+        &mut self,
+        farm_id: FarmId,
+        farmer_id: AccountId,
+        token_ids: Vec<NFTTokenId>,
+        withdraw_token_id: Option<NFTTokenId>,
+    ) -> Promise {
+        let nft_contract_id = self.internal_unwrap_farm(&farm_id).nft_contract_id;
+        let mut promise = ext_nft::nft_token(token_ids[0].clone(), &nft_contract_id, 0, GAS_FOR_NFT_VIEW);
+        for token_id in token_ids.iter().skip(1) {
+            promise = promise.and(ext_nft::nft_token(
+                token_id.clone(),
+                &nft_contract_id,
+                0,
+                GAS_FOR_NFT_VIEW,
+            ));
+        }
+        promise.then(ext_self::callback_verify_approved_nfts(
+            farm_id,
+            farmer_id,
+            token_ids,
+            withdraw_token_id,
+            &env::current_account_id(),
+            0,
+            GAS_FOR_VERIFY_CALLBACK,
+        ))
+    }
+
+    /// Unstakes NFTs staked by approval which moved or lost the approval,
+    /// the farmer gives up all unpaid reward in this farm.
+    fn internal_forfeit_approved_nfts(
+        &mut self,
+        farm_id: &FarmId,
+        farmer_id: &AccountId,
+        token_ids: &[NFTTokenId],
+        new_staked_at: Timestamp,
+    ) {
+        let mut farmer = self.internal_unwrap_farmer(farmer_id);
+        let mut farm = self.internal_unwrap_farm(farm_id);
+        let mut staking_info = farmer.staking.get(farm_id).unwrap();
+        farm.checkpoint_reward(&mut staking_info, new_staked_at);
+
+        for token_id in token_ids {
+            if let Some(token) = farm.staked_nfts.get(token_id) {
+                if &token.owner_id == farmer_id {
+                    farm.staked_nfts.remove(token_id);
+                    staking_info.token_balances.remove(token_id);
+                    staking_info.amount -= 1;
+                    self.approved_nfts
+                        .remove(&contract_token_id(&farm.nft_contract_id, token_id));
+                }
+            }
+        }
+        farm.update_completed_sets(&mut staking_info);
+        farm.add_stream_weight(&staking_info);
+        staking_info.pending_reward = 0;
+        staking_info.pending_stream_reward = 0;
+        farmer.staking.insert(farm_id, &staking_info);
+        self.farmers.insert(farmer_id, &farmer);
+        self.farms.insert(farm_id, &farm);
+        env::log(
+            format!(
+                "Reward of {} in farm {} is forfeited, approved NFTs moved",
+                farmer_id, farm_id
+            )
+            .as_bytes(),
+        );
+    }
+
+    /// Errors when the farm or the wallet is full, so that the NFT can
+    /// be given back, other failures panic.
     pub fn internal_stake(
         &mut self,
         farm_id: FarmId,
         prev_owner: AccountId,
         nft_contract_id: AccountId,
         token_id: NFTTokenId,
-    ) {
+        approval_id: Option<u64>,
+    ) -> Result<(), FarmingError> {
         let new_staked_at = env::block_timestamp();
-        let mut farmer = self.farmers.get(&prev_owner).unwrap();
-        let mut farm = self.farms.get(&farm_id).unwrap();
+        let mut farmer = self.internal_unwrap_farmer(&prev_owner);
+        let mut farm = self.internal_unwrap_farm(&farm_id);
 
-        assert!(farm.status != Status::Ended, "This farm is ended");
-        assert!(
-            farm.accepted_nfts.contains(&token_id),
-            "This farm is not accept your NFT"
-        );
-        assert_eq!(
-            farm.nft_contract_id, nft_contract_id,
-            "This farm is not accept your NFT"
-        );
+        require(farm.status != Status::Ended, FarmingError::FarmEnded);
+        if farm.is_hybrid() {
+            require(approval_id.is_none(), FarmingError::ApprovalNotSupported);
+            self.internal_stake_boost_nft(farm_id, prev_owner, nft_contract_id, token_id);
+            return Ok(());
+        }
+        require(farm.kind == FarmKind::NonFungible, FarmingError::WrongFarmKind);
+        require(farm.accepted_nfts.contains(&token_id), FarmingError::TokenNotAccepted);
+        require(farm.nft_contract_id == nft_contract_id, FarmingError::TokenNotAccepted);
+
+        require(farm.staked_nfts.get(&token_id).is_none(), FarmingError::AlreadyStaked);
+        if farm.remaining_capacity() == Some(0) {
+            return Err(FarmingError::FarmFull);
+        }
+        if let Some(max_per_wallet) = farm.terms.max_per_wallet {
+            let staked = farmer.staking.get(&farm_id).map_or(0, |staking_info| staking_info.amount);
+            if staked >= max_per_wallet as Balance {
+                return Err(FarmingError::WalletCapReached);
+            }
+        }
+        if approval_id.is_some() {
+            // the approved NFTs of a farmer are verified in one call
+            let approved = farmer
+                .staking
+                .get(&farm_id)
+                .map_or(0, |staking_info| farm.approved_nfts_of(&staking_info).len());
+            require(approved < MAX_VERIFY_NFTS, FarmingError::TooManyNfts);
+            // an approved NFT stays with its owner, who could approve it for another farm
+            let contract_token_id = contract_token_id(&nft_contract_id, &token_id);
+            require(
+                self.approved_nfts.get(&contract_token_id).is_none(),
+                FarmingError::AlreadyStaked,
+            );
+            self.approved_nfts.insert(&contract_token_id, &farm_id);
+        }
 
         let farm_staked_info = StakedInfo {
             owner_id: prev_owner.clone(),
             staked_at: new_staked_at,
+            approval_id,
         };
 
         if let Some(mut staking_info) = farmer.staking.get(&farm_id) {
             let will_claim_amount = self.internal_get_claimable(&farm_id, &prev_owner, new_staked_at);
-            assert_ne!(
-                will_claim_amount, farm.amount_of_reward,
-                "Farm\'s reward is almost over. Please claim your rewards as soon as possible"
+            require(
+                will_claim_amount != farm.amount_of_reward + farm.stream_reward,
+                FarmingError::RewardAlmostOver,
             );
             farm.staked_nfts.insert(&token_id, &farm_staked_info);
+            farm.checkpoint_reward(&mut staking_info, new_staked_at);
             staking_info.amount += 1;
+            staking_info.token_balances.insert(token_id, 1);
+            farm.update_completed_sets(&mut staking_info);
+            farm.add_stream_weight(&staking_info);
             farmer.staking.insert(&farm_id, &staking_info);
             
             self.farmers.insert(&prev_owner, &farmer);
             self.farms.insert(&farm_id, &farm);
             if will_claim_amount != 0 {
-                self.internal_claim_reward_by_farm(
+                self.internal_auto_claim(
                     &farm_id,
                     &prev_owner,
                     will_claim_amount,
@@ -179,70 +1054,337 @@ impl Contract {
             if new_staked_at > farm.terms.start_at {
                 time_staked = new_staked_at;
             }
-            let new_staking_info = StakingInfo {
-                last_staked_at: time_staked,
-                amount: 1,
-            };
+            let mut new_staking_info = StakingInfo::new(time_staked);
+            farm.checkpoint_reward(&mut new_staking_info, new_staked_at);
+            new_staking_info.amount = 1;
+            new_staking_info.token_balances.insert(token_id.clone(), 1);
+            farm.update_completed_sets(&mut new_staking_info);
+            farm.add_stream_weight(&new_staking_info);
 
             farm.staked_nfts.insert(&token_id, &farm_staked_info);
             farmer.staking.insert(&farm_id, &new_staking_info);
             self.farmers.insert(&prev_owner, &farmer);
             self.farms.insert(&farm_id, &farm);
         }
+        Ok(())
     }
 
     pub fn internal_withdraw(
         &mut self,
+        sender_id: AccountId,
         farm_id: FarmId,
         token_id: NFTTokenId
     ) {
-        let sender_id = env::predecessor_account_id();
         let new_staked_at = env::block_timestamp();
-        let mut farmer = self.farmers.get(&sender_id).unwrap();
-        let mut farm = self.farms.get(&farm_id).unwrap();
+        let mut farmer = self.internal_unwrap_farmer(&sender_id);
+        let mut farm = self.internal_unwrap_farm(&farm_id);
 
         if let Some(token) = farm.staked_nfts.get(&token_id) {
-            assert_eq!(
-                token.owner_id, sender_id,
-                "You are not the owner of this NFT"
-            );
+            require(token.owner_id == sender_id, FarmingError::NotNftOwner);
             if (new_staked_at - token.staked_at) >= farm.terms.session_interval
                 && new_staked_at > farm.terms.start_at
             {
                 let will_claim_amount =
                     self.internal_get_claimable(&farm_id, &sender_id, new_staked_at);
                 let mut staking_info = farmer.staking.get(&farm_id).unwrap();
-                staking_info.last_staked_at = new_staked_at;
-                staking_info.amount -= 1;
+                farm.checkpoint_reward(&mut staking_info, new_staked_at);
+                if farm.is_hybrid() {
+                    staking_info.boost_bps = 0;
+                } else {
+                    staking_info.amount -= 1;
+                    staking_info.token_balances.remove(&token_id);
+                }
+                farm.update_completed_sets(&mut staking_info);
+                farm.add_stream_weight(&staking_info);
                 farm.staked_nfts.remove(&token_id);
                 farmer.staking.insert(&farm_id, &staking_info);
                 self.farmers.insert(&sender_id, &farmer);
                 self.farms.insert(&farm_id, &farm);
                 
                 if will_claim_amount != 0 {
-                    self.internal_claim_reward_by_farm(
+                    self.internal_auto_claim(
                         &farm_id,
                         &sender_id,
                         will_claim_amount,
                         new_staked_at
                     );
                 }
-                self.internal_withdraw_nft(token_id, &sender_id, farm.nft_contract_id.clone());
+                if farm.is_hybrid() {
+                    let (nft_contract_id, token_id) = parse_contract_token_id(&token_id);
+                    self.internal_withdraw_nft(token_id, &sender_id, nft_contract_id);
+                } else if token.approval_id.is_some() {
+                    // the NFT never left its owner
+                    self.approved_nfts
+                        .remove(&contract_token_id(&farm.nft_contract_id, &token_id));
+                } else {
+                    self.internal_withdraw_nft(token_id, &sender_id, farm.nft_contract_id.clone());
+                }
             } else {
-                env::panic(b"Not time for withdraw");
+                FarmingError::NftLocked.panic();
             }
         } else {
-            env::panic(b"Not found this NFT in farm");
+            FarmingError::NftNotFound.panic();
         }
     }
 
+    /// Stakes the NFT boosting the yield of a hybrid farm.
+    /// The NFT is kept under its `ContractNFTTokenId` in `staked_nfts`.
+    fn internal_stake_boost_nft(
+        &mut self,
+        farm_id: FarmId,
+        prev_owner: AccountId,
+        nft_contract_id: AccountId,
+        token_id: NFTTokenId,
+    ) {
+        let new_staked_at = env::block_timestamp();
+        let mut farmer = self.internal_unwrap_farmer(&prev_owner);
+        let mut farm = self.internal_unwrap_farm(&farm_id);
+        let boost_bps = self.get_nft_boost(nft_contract_id.clone());
+
+        require(
+            self.is_whitelist_nft_contract(&nft_contract_id) && boost_bps > 0,
+            FarmingError::TokenNotAccepted,
+        );
+
+        // the reward accrued so far stays pending, as in `internal_stake_ft`
+        let mut staking_info = farmer
+            .staking
+            .get(&farm_id)
+            .unwrap_or(StakingInfo::new(new_staked_at));
+        require(staking_info.boost_bps == 0, FarmingError::AlreadyBoosted);
+        farm.checkpoint_reward(&mut staking_info, new_staked_at);
+        staking_info.boost_bps = boost_bps;
+        farm.add_stream_weight(&staking_info);
+
+        farm.staked_nfts.insert(
+            &contract_token_id(&nft_contract_id, &token_id),
+            &StakedInfo {
+                owner_id: prev_owner.clone(),
+                staked_at: new_staked_at,
+                approval_id: None,
+            },
+        );
+        farmer.staking.insert(&farm_id, &staking_info);
+        self.farmers.insert(&prev_owner, &farmer);
+        self.farms.insert(&farm_id, &farm);
+    }
+
+    pub fn internal_stake_ft(
+        &mut self,
+        farm_id: FarmId,
+        sender_id: AccountId,
+        ft_contract_id: AccountId,
+        amount: Balance,
+    ) -> Result<(), FarmingError> {
+        let new_staked_at = env::block_timestamp();
+        let mut farmer = self
+            .farmers
+            .get(&sender_id)
+            .ok_or(FarmingError::FarmerNotRegistered)?;
+        let mut farm = self.farms.get(&farm_id).ok_or(FarmingError::FarmNotFound)?;
+
+        if farm.status == Status::Ended {
+            return Err(FarmingError::FarmEnded);
+        }
+        if farm.ft_contract_id() != Some(&ft_contract_id) {
+            return Err(FarmingError::TokenNotAccepted);
+        }
+
+        // the reward accrued so far stays pending until claimed, paying it here
+        // could fail after the tokens are taken
+        let mut staking_info = farmer
+            .staking
+            .get(&farm_id)
+            .unwrap_or(StakingInfo::new(new_staked_at));
+        farm.checkpoint_reward(&mut staking_info, new_staked_at);
+        staking_info.amount += amount;
+        farm.total_staked += amount;
+        farm.add_stream_weight(&staking_info);
+        farmer.staking.insert(&farm_id, &staking_info);
+        self.farmers.insert(&sender_id, &farmer);
+        self.farms.insert(&farm_id, &farm);
+        Ok(())
+    }
+
+    pub fn internal_unstake_ft(
+        &mut self,
+        farm_id: FarmId,
+        amount: Balance
+    ) -> Promise {
+        let sender_id = env::predecessor_account_id();
+        let new_staked_at = env::block_timestamp();
+        let mut farmer = self.internal_unwrap_farmer(&sender_id);
+        let mut farm = self.internal_unwrap_farm(&farm_id);
+        let ft_contract_id = farm.ft_contract_id().cloned().unwrap_or_else(|| FarmingError::WrongFarmKind.panic());
+        let mut staking_info = farmer.staking.get(&farm_id).unwrap_or_else(|| FarmingError::NotStaked.panic());
+
+        require(amount != 0, FarmingError::InvalidAmount);
+        require(staking_info.amount >= amount, FarmingError::NotEnoughStaked);
+
+        let will_claim_amount = self.internal_get_claimable(&farm_id, &sender_id, new_staked_at);
+        farm.checkpoint_reward(&mut staking_info, new_staked_at);
+        staking_info.amount -= amount;
+        farm.total_staked -= amount;
+        farm.add_stream_weight(&staking_info);
+        farmer.staking.insert(&farm_id, &staking_info);
+        self.farmers.insert(&sender_id, &farmer);
+        self.farms.insert(&farm_id, &farm);
+
+        if will_claim_amount != 0 {
+            self.internal_claim_reward_by_farm(
+                &farm_id,
+                &sender_id,
+                will_claim_amount,
+                new_staked_at
+            );
+        }
+        self.internal_withdraw_ft(amount, &sender_id, ft_contract_id)
+            .then(ext_self::callback_resolve_unstake_ft(
+                farm_id,
+                sender_id,
+                U128(amount),
+                &env::current_account_id(),
+                0,
+                GAS_FOR_RESOLVE_UNSTAKE,
+            ))
+    }
+
+    pub fn internal_stake_mt(
+        &mut self,
+        farm_id: FarmId,
+        prev_owner: AccountId,
+        mt_contract_id: AccountId,
+        token_ids: &[NFTTokenId],
+        amounts: &[U128],
+    ) {
+        let new_staked_at = env::block_timestamp();
+        let mut farmer = self.internal_unwrap_farmer(&prev_owner);
+        let mut farm = self.internal_unwrap_farm(&farm_id);
+
+        require(farm.status != Status::Ended, FarmingError::FarmEnded);
+        require(
+            farm.mt_contract_id() == Some(&mt_contract_id),
+            FarmingError::TokenNotAccepted,
+        );
+        require(token_ids.len() == amounts.len(), FarmingError::WrongTokenAmounts);
+
+        // the reward accrued so far stays pending, as in `internal_stake_ft`
+        let mut staking_info = farmer
+            .staking
+            .get(&farm_id)
+            .unwrap_or(StakingInfo::new(new_staked_at));
+        farm.checkpoint_reward(&mut staking_info, new_staked_at);
+        for (token_id, amount) in token_ids.iter().zip(amounts.iter()) {
+            require(farm.accepted_nfts.contains(token_id), FarmingError::TokenNotAccepted);
+            *staking_info.token_balances.entry(token_id.clone()).or_insert(0) += amount.0;
+            staking_info.amount += amount.0;
+            farm.total_staked += amount.0;
+        }
+        farm.add_stream_weight(&staking_info);
+        farmer.staking.insert(&farm_id, &staking_info);
+        self.farmers.insert(&prev_owner, &farmer);
+        self.farms.insert(&farm_id, &farm);
+    }
+
+    pub fn internal_unstake_mt(
+        &mut self,
+        farm_id: FarmId,
+        token_ids: Vec<NFTTokenId>,
+        amounts: Vec<U128>,
+    ) -> Promise {
+        let sender_id = env::predecessor_account_id();
+        let new_staked_at = env::block_timestamp();
+        let mut farmer = self.internal_unwrap_farmer(&sender_id);
+        let mut farm = self.internal_unwrap_farm(&farm_id);
+        let mt_contract_id = farm.mt_contract_id().cloned().unwrap_or_else(|| FarmingError::WrongFarmKind.panic());
+        let mut staking_info = farmer.staking.get(&farm_id).unwrap_or_else(|| FarmingError::NotStaked.panic());
+
+        require(!token_ids.is_empty(), FarmingError::InvalidAmount);
+        require(token_ids.len() == amounts.len(), FarmingError::WrongTokenAmounts);
+
+        let will_claim_amount = self.internal_get_claimable(&farm_id, &sender_id, new_staked_at);
+        farm.checkpoint_reward(&mut staking_info, new_staked_at);
+        for (token_id, amount) in token_ids.iter().zip(amounts.iter()) {
+            let balance = staking_info.token_balances.get(token_id).cloned().unwrap_or(0);
+            require(amount.0 != 0, FarmingError::InvalidAmount);
+            require(balance >= amount.0, FarmingError::NotEnoughStaked);
+            if balance == amount.0 {
+                staking_info.token_balances.remove(token_id);
+            } else {
+                staking_info.token_balances.insert(token_id.clone(), balance - amount.0);
+            }
+            staking_info.amount -= amount.0;
+            farm.total_staked -= amount.0;
+        }
+        farm.add_stream_weight(&staking_info);
+        farmer.staking.insert(&farm_id, &staking_info);
+        self.farmers.insert(&sender_id, &farmer);
+        self.farms.insert(&farm_id, &farm);
+
+        if will_claim_amount != 0 {
+            self.internal_claim_reward_by_farm(
+                &farm_id,
+                &sender_id,
+                will_claim_amount,
+                new_staked_at
+            );
+        }
+        self.internal_withdraw_mt(token_ids.clone(), amounts.clone(), &sender_id, mt_contract_id)
+            .then(ext_self::callback_resolve_unstake_mt(
+                farm_id,
+                sender_id,
+                token_ids,
+                amounts,
+                &env::current_account_id(),
+                0,
+                GAS_FOR_RESOLVE_UNSTAKE,
+            ))
+    }
+
+    /// Adds the NFTs when `deposit` pays their storage, the rest of it
+    /// is refunded to the farm owner.
+    fn internal_add_accepted_nfts(
+        &mut self,
+        farm_id: FarmId,
+        tokens: HashSet<NFTTokenId>,
+        deposit: Balance,
+    ) -> Result<(), FarmingError> {
+        let prev_storage = env::storage_usage();
+        let mut farm = self.internal_unwrap_farm(&farm_id);
+        let prev_accepted_nfts = farm.accepted_nfts.clone();
+        let prev_set_sizes = farm.set_sizes.clone();
+        for token_id in tokens.iter() {
+            farm.set_nft_accepted(token_id, true);
+        }
+        farm.touch_sets(env::block_timestamp());
+        self.farms.insert(&farm_id, &farm);
+
+        let storage_cost = env::storage_usage().saturating_sub(prev_storage) as Balance
+            * env::storage_byte_cost();
+        if deposit < storage_cost {
+            farm.accepted_nfts = prev_accepted_nfts;
+            farm.set_sizes = prev_set_sizes;
+            self.farms.insert(&farm_id, &farm);
+            return Err(FarmingError::NotEnoughStorageDeposit);
+        }
+        if deposit > storage_cost {
+            Promise::new(farm.owner_id).transfer(deposit - storage_cost);
+        }
+        Ok(())
+    }
+
     fn internal_add_farm(
         &mut self,
         owner_id: AccountId,
         terms: TermsJson,
-        nft_contract_id: &AccountId,
+        kind: FarmKind,
+        nft_contract_id: AccountId,
         accepted_nfts: HashSet<ContractNFTTokenId>,
     ) -> FarmId {
+        require(
+            self.reward_tokens.contains(&terms.seed_id),
+            FarmingError::RewardTokenNotAllowed,
+        );
         let mut seed: Seed;
         if let Some(s) = self.seeds.get(&terms.seed_id.clone()) {
             seed = s;
@@ -253,19 +1395,31 @@ impl Contract {
                 format!("The first farm created In seed {}", terms.seed_id.clone()).as_bytes(),
             );
         }
+        if kind == FarmKind::NonFungible {
+            self.internal_add_nft_contract_farm(&nft_contract_id);
+        }
         let seed_id = terms.seed_id.clone();
-        let farm_id: FarmId = format!("{}#{}", &terms.seed_id.clone(), seed.next_index as usize);
+        let farm_id = self.next_farm_id;
+        self.next_farm_id += 1;
+        // farms were named `seed_id#index` before they had a global id
+        let legacy_farm_id = ValidFarmId::Legacy {
+            seed_id: seed_id.clone(),
+            index: seed.next_index,
+        };
+        self.legacy_farm_ids.insert(&legacy_farm_id.to_string(), &farm_id);
         let farm = Farm::new(
             owner_id,
-            farm_id.clone(),
+            farm_id,
+            kind,
             terms.into(),
-            nft_contract_id.into(),
+            nft_contract_id,
             accepted_nfts,
         );
 
         seed.next_index += 1;
+        seed.farm_ids.push(farm_id);
         self.seeds.insert(&seed_id, &seed);
-        self.farms.insert(&farm_id.clone(), &farm);
+        self.farms.insert(&farm_id, &farm);
         farm_id
     }
 
@@ -277,24 +1431,62 @@ impl Contract {
     ) -> Balance {
         let mut will_claim_amount: Balance = 0;
         if let Some(farm) = self.farms.get(&farm_id) {
-            if let Some(staking_info) = self.farmers.get(farmer_id).unwrap().staking.get(&farm_id)
+            if let Some(staking_info) = self
+                .farmers
+                .get(farmer_id)
+                .and_then(|farmer| farmer.staking.get(&farm_id))
             {
-                if (new_staked_at - staking_info.last_staked_at) > farm.terms.session_interval
-                    && farm.status == Status::Running
-                {
-                    will_claim_amount = staking_info.amount * farm.terms.reward_per_session;
-                    will_claim_amount *=
-                        (new_staked_at - staking_info.last_staked_at) as u128;
-                    will_claim_amount /= farm.terms.session_interval as u128;
-                    if will_claim_amount > farm.amount_of_reward {
-                        will_claim_amount = farm.amount_of_reward;
+                let mut stream_amount = staking_info.pending_stream_reward;
+                if farm.status == Status::Running {
+                    if (new_staked_at - staking_info.last_staked_at) > farm.terms.session_interval {
+                        will_claim_amount = farm.session_reward_until(&staking_info, new_staked_at);
                     }
+                    stream_amount += farm.stream_reward_of(&staking_info, new_staked_at);
                 }
+                will_claim_amount = will_claim_amount.saturating_add(staking_info.pending_reward);
+                // each reward is paid only from its own funds
+                will_claim_amount = std::cmp::min(will_claim_amount, farm.amount_of_reward)
+                    + std::cmp::min(stream_amount, farm.stream_reward);
             }
         }
         will_claim_amount
     }
 
+    pub(crate) fn internal_unwrap_farm(&self, farm_id: &FarmId) -> Farm {
+        self.farms
+            .get(farm_id)
+            .unwrap_or_else(|| FarmingError::FarmNotFound.panic())
+    }
+
+    pub(crate) fn internal_unwrap_farmer(&self, account_id: &AccountId) -> Farmer {
+        self.farmers
+            .get(account_id)
+            .unwrap_or_else(|| FarmingError::FarmerNotRegistered.panic())
+    }
+
+    pub(crate) fn internal_resolve_farm_id(&self, farm_id: &ValidFarmId) -> Option<FarmId> {
+        match farm_id {
+            ValidFarmId::Index(index) => Some(*index),
+            ValidFarmId::Legacy { .. } => self.legacy_farm_ids.get(&farm_id.to_string()),
+        }
+    }
+
+    /// Farm id of an index or a legacy `seed_id#index`, `None` if malformed or unknown.
+    pub(crate) fn internal_parse_farm_id(&self, farm_id: &str) -> Option<FarmId> {
+        self.internal_resolve_farm_id(&ValidFarmId::try_from(farm_id).ok()?)
+    }
+
+    pub(crate) fn internal_unwrap_farm_id(&self, farm_id: &str) -> FarmId {
+        let farm_id = ValidFarmId::try_from(farm_id).unwrap_or_else(|error| error.panic());
+        self.internal_resolve_farm_id(&farm_id)
+            .unwrap_or_else(|| FarmingError::FarmNotFound.panic())
+    }
+
+    /// Farm of `farm_id` in JSON, `None` instead of panicking.
+    pub fn internal_get_farm(&self, farm_id: &str) -> Option<Farm> {
+        self.farms.get(&self.internal_parse_farm_id(farm_id)?)
+    }
+
     pub fn internal_withdraw_nft(
         &mut self,
         token_id: NFTTokenId,
@@ -311,4 +1503,39 @@ impl Contract {
             GAS_FOR_NFT_TRANSFER
         )
     }
+
+    pub fn internal_withdraw_ft(
+        &mut self,
+        amount: Balance,
+        sender_id: &AccountId,
+        ft_contract_id: AccountId,
+    ) -> Promise {
+        ext_ft::ft_transfer(
+            sender_id.clone(),
+            amount.to_string(),
+            None,
+            &ft_contract_id,
+            1,
+            GAS_FOR_FT_TRANSFER
+        )
+    }
+
+    pub fn internal_withdraw_mt(
+        &mut self,
+        token_ids: Vec<NFTTokenId>,
+        amounts: Vec<U128>,
+        sender_id: &AccountId,
+        mt_contract_id: AccountId,
+    ) -> Promise {
+        ext_mt::mt_batch_transfer(
+            sender_id.clone(),
+            token_ids,
+            amounts,
+            None,
+            None,
+            &mt_contract_id,
+            1,
+            GAS_FOR_MT_TRANSFER
+        )
+    }
 }
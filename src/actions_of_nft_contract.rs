@@ -0,0 +1,137 @@
+use crate::nft_contract::{CreatorMode, NftContractConfig};
+use crate::role::Role;
+use crate::*;
+use near_sdk::json_types::ValidAccountId;
+use near_sdk::{env, near_bindgen, AccountId};
+
+#[near_bindgen]
+impl Contract {
+    /// Whitelists a NFT contract with the default config, or enables it again.
+    #[payable]
+    pub fn whitelist_nft_contract(&mut self, nft_contract_id: ValidAccountId) {
+        self.assert_role(Role::Operator);
+        let nft_contract_id: AccountId = nft_contract_id.into();
+        let mut config = self
+            .nft_contracts
+            .get(&nft_contract_id)
+            .unwrap_or_else(|| NftContractConfig::new(nft_contract_id.clone()));
+        config.enabled = true;
+        self.nft_contracts.insert(&nft_contract_id, &config);
+    }
+
+    /// Adds or replaces the config of a NFT contract, `farm_count` is kept.
+    pub fn set_nft_contract(&mut self, nft_contract_id: ValidAccountId, config: NftContractConfig) {
+        self.assert_role(Role::Operator);
+        let nft_contract_id: AccountId = nft_contract_id.into();
+        let mut config = config;
+        config.farm_count = self
+            .nft_contracts
+            .get(&nft_contract_id)
+            .map_or(0, |old_config| old_config.farm_count);
+        self.nft_contracts.insert(&nft_contract_id, &config);
+    }
+
+    /// Existing farms of the contract keep running, no new farm can be created.
+    pub fn remove_nft_contract(&mut self, nft_contract_id: ValidAccountId) -> bool {
+        self.assert_role(Role::Operator);
+        let nft_contract_id: AccountId = nft_contract_id.into();
+        self.nft_contracts.remove(&nft_contract_id).is_some()
+    }
+
+    pub fn set_creator_mode(&mut self, nft_contract_id: ValidAccountId, creator_mode: CreatorMode) {
+        self.assert_role(Role::FarmManager);
+        let nft_contract_id: AccountId = nft_contract_id.into();
+        let mut config = self.internal_get_nft_contract(&nft_contract_id);
+        config.creator_mode = creator_mode;
+        self.nft_contracts.insert(&nft_contract_id, &config);
+    }
+
+    /// Admins may create farms of a NFT contract in `CreatorMode::Admins`.
+    pub fn add_nft_contract_admin(&mut self, nft_contract_id: ValidAccountId, account_id: ValidAccountId) {
+        self.assert_role(Role::FarmManager);
+        let nft_contract_id: AccountId = nft_contract_id.into();
+        let mut config = self.internal_get_nft_contract(&nft_contract_id);
+        config.farm_creators.insert(account_id.into());
+        self.nft_contracts.insert(&nft_contract_id, &config);
+    }
+
+    pub fn remove_nft_contract_admin(&mut self, nft_contract_id: ValidAccountId, account_id: ValidAccountId) {
+        self.assert_role(Role::FarmManager);
+        let nft_contract_id: AccountId = nft_contract_id.into();
+        let mut config = self.internal_get_nft_contract(&nft_contract_id);
+        let account_id: AccountId = account_id.into();
+        config.farm_creators.remove(&account_id);
+        self.nft_contracts.insert(&nft_contract_id, &config);
+    }
+
+    /// Bonus in basis points given in hybrid farms by staking a NFT of this contract.
+    pub fn set_nft_boost(&mut self, nft_contract_id: ValidAccountId, boost_bps: u32) {
+        self.assert_role(Role::Operator);
+        let nft_contract_id: AccountId = nft_contract_id.into();
+        let mut config = self.internal_get_nft_contract(&nft_contract_id);
+        config.boost_bps = boost_bps;
+        self.nft_contracts.insert(&nft_contract_id, &config);
+    }
+
+    /// View methods.
+    pub fn is_whitelist_nft_contract(&self, nft_contract_id: &AccountId) -> bool {
+        self.nft_contracts
+            .get(nft_contract_id)
+            .map_or(false, |config| config.enabled)
+    }
+
+    pub fn get_nft_contract(&self, nft_contract_id: AccountId) -> Option<NftContractConfig> {
+        self.nft_contracts.get(&nft_contract_id)
+    }
+
+    pub fn list_nft_contracts(&self, from_index: u64, limit: u64) -> Vec<(AccountId, NftContractConfig)> {
+        let keys = self.nft_contracts.keys_as_vector();
+        let values = self.nft_contracts.values_as_vector();
+
+        (from_index..std::cmp::min(from_index + limit, self.nft_contracts.len()))
+            .map(|index| (keys.get(index).unwrap(), values.get(index).unwrap()))
+            .collect()
+    }
+
+    pub fn get_creator_mode(&self, nft_contract_id: AccountId) -> CreatorMode {
+        self.nft_contracts
+            .get(&nft_contract_id)
+            .map(|config| config.creator_mode)
+            .unwrap_or_default()
+    }
+
+    pub fn get_nft_contract_admins(&self, nft_contract_id: AccountId) -> Vec<AccountId> {
+        self.nft_contracts
+            .get(&nft_contract_id)
+            .map(|config| config.farm_creators.into_iter().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn is_nft_contract_admin(&self, nft_contract_id: &AccountId, account_id: &AccountId) -> bool {
+        self.nft_contracts
+            .get(nft_contract_id)
+            .map_or(false, |config| config.farm_creators.contains(account_id))
+    }
+
+    pub fn get_nft_boost(&self, nft_contract_id: AccountId) -> u32 {
+        self.nft_contracts
+            .get(&nft_contract_id)
+            .map_or(0, |config| config.boost_bps)
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_get_nft_contract(&self, nft_contract_id: &AccountId) -> NftContractConfig {
+        self.nft_contracts
+            .get(nft_contract_id)
+            .unwrap_or_else(|| FarmingError::NftContractNotWhitelisted.panic())
+    }
+
+    /// Counts a new farm of the NFT contract against its `max_farms`.
+    pub(crate) fn internal_add_nft_contract_farm(&mut self, nft_contract_id: &AccountId) {
+        let mut config = self.internal_get_nft_contract(nft_contract_id);
+        require(config.can_add_farm(), FarmingError::TooManyFarms);
+        config.farm_count += 1;
+        self.nft_contracts.insert(nft_contract_id, &config);
+    }
+}
@@ -1,23 +1,439 @@
-use near_sdk::{env, AccountId, Balance, Promise};
-use std::convert::TryInto;
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_contract_standards::storage_management::StorageBalance;
+use near_sdk::{assert_one_yocto, env, near_bindgen, serde_json, AccountId, Balance, Promise, PromiseResult, Timestamp};
+use std::collections::HashMap;
 
-use crate::utils::{ext_ft, GAS_FOR_FT_TRANSFER};
+use crate::errors::{require, FarmingError};
+use crate::farm::{Status, Stream, VestingTerms, BP_DENOMINATOR, CLOSE_NOTICE, MAX_STREAMS};
+use crate::utils::{
+    ext_ft, ext_self, mul_div, FT_STORAGE_DEPOSIT, GAS_FOR_CLAIM_CALLBACK, GAS_FOR_CLAIM_FARM,
+    GAS_FOR_CLAIM_REWARD, GAS_FOR_FT_TRANSFER, GAS_FOR_FT_VIEW, GAS_FOR_RESOLVE_REWARD,
+};
 use crate::*;
 
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ClaimAllResult {
+    /// Paid amount of each reward token.
+    pub claimed: Vec<(SeedId, U128)>,
+    /// Index to continue from when gas ran out.
+    pub next_index: Option<u64>,
+}
+
+#[near_bindgen]
 impl Contract {
+    /// Claims reward of `farm_ids`, or of all farms of the farmer, with one
+    /// transfer per reward token. Farms are settled from `from_index` while
+    /// gas is left, `next_index` is set when some of them are not reached.
+    /// Farms with NFTs staked by approval need `claim_reward_by_farm`.
+    #[payable]
+    pub fn claim_all(&mut self, farm_ids: Option<Vec<FarmIdJson>>, from_index: Option<u64>) -> ClaimAllResult {
+        self.assert_running();
+        assert_one_yocto();
+        let sender_id = env::predecessor_account_id();
+        let new_staked_at = env::block_timestamp();
+        let farm_ids: Vec<Option<FarmId>> = match farm_ids {
+            Some(farm_ids) => farm_ids
+                .iter()
+                .map(|farm_id| self.internal_parse_farm_id(farm_id))
+                .collect(),
+            None => self
+                .internal_unwrap_farmer(&sender_id)
+                .staking
+                .keys_as_vector()
+                .iter()
+                .map(Some)
+                .collect(),
+        };
+
+        // reward of several farms of a seed goes back into the first of them
+        // when it can not be paid
+        let mut payouts: HashMap<SeedId, (FarmId, Balance)> = HashMap::new();
+        let mut next_index = None;
+        for index in from_index.unwrap_or(0)..farm_ids.len() as u64 {
+            let reserved_gas = GAS_FOR_CLAIM_FARM + GAS_FOR_CLAIM_REWARD * (payouts.len() as u64 + 1);
+            if env::prepaid_gas() - env::used_gas() < reserved_gas {
+                next_index = Some(index);
+                break;
+            }
+
+            let farm_id = match &farm_ids[index as usize] {
+                Some(farm_id) => farm_id,
+                None => continue,
+            };
+            let farm = match self.farms.get(farm_id) {
+                Some(farm) => farm,
+                None => continue,
+            };
+            if farm.status != Status::Running
+                || farm.terms.start_at > new_staked_at
+                || !self.internal_approved_nfts(farm_id, &sender_id).is_empty()
+            {
+                continue;
+            }
+            let will_claim_amount = self.internal_get_claimable(farm_id, &sender_id, new_staked_at);
+            if will_claim_amount == 0 {
+                continue;
+            }
+            let paid_amount = self.internal_settle_reward(farm_id, &sender_id, will_claim_amount, new_staked_at);
+            payouts
+                .entry(farm.terms.seed_id.clone())
+                .or_insert((*farm_id, 0))
+                .1 += paid_amount;
+        }
+
+        let mut claimed = vec![];
+        for (seed_id, (farm_id, amount)) in payouts {
+            if amount != 0 {
+                self.internal_claim_user_reward(amount, &sender_id, &seed_id, Some(farm_id));
+                claimed.push((seed_id, U128(amount)));
+            }
+        }
+        ClaimAllResult { claimed, next_index }
+    }
+
+    pub fn set_funding_policy(&mut self, farm_id: FarmIdJson, funding_policy: FundingPolicy) {
+        let farm_id = self.internal_unwrap_farm_id(&farm_id);
+        let mut farm = self.internal_unwrap_farm(&farm_id);
+        require(
+            env::predecessor_account_id() == farm.owner_id,
+            FarmingError::NotFarmOwner,
+        );
+        farm.funding_policy = funding_policy;
+        self.farms.insert(&farm_id, &farm);
+    }
+
+    /// Ends the farm, the reward left becomes refundable to its funders
+    /// with `claim_refund`. A running farm with stake stops its reward at
+    /// once and can be ended after `CLOSE_NOTICE`, so that stakers can claim.
+    pub fn close_farm(&mut self, farm_id: FarmIdJson) {
+        self.assert_running();
+        let farm_id = self.internal_unwrap_farm_id(&farm_id);
+        let mut farm = self.internal_unwrap_farm(&farm_id);
+        require(
+            env::predecessor_account_id() == farm.owner_id,
+            FarmingError::NotFarmOwner,
+        );
+        require(farm.status != Status::Ended, FarmingError::FarmEnded);
+
+        let now = env::block_timestamp();
+        if farm.status == Status::Running && !farm.is_empty() {
+            match farm.closing_at {
+                None => {
+                    farm.start_closing(now);
+                    self.farms.insert(&farm_id, &farm);
+                    env::log(
+                        format!("Farm {} stopped its reward, it can be closed at {}", farm_id, now + CLOSE_NOTICE)
+                            .as_bytes(),
+                    );
+                    return;
+                }
+                Some(closing_at) => require(closing_at <= now, FarmingError::FarmClosing),
+            }
+        }
+
+        let was_running = farm.status == Status::Running;
+        farm.refundable = farm.amount_of_reward + farm.stream_reward;
+        farm.amount_of_reward = 0;
+        farm.stream_reward = 0;
+        farm.streams.clear();
+        farm.status = Status::Ended;
+        self.farms.insert(&farm_id, &farm);
+        self.internal_add_seed_claim(&farm.terms.seed_id, 0, was_running);
+        env::log(
+            format!("Farm {} closed, {} is refundable", farm_id, farm.refundable).as_bytes(),
+        );
+    }
+
+    /// Pays the share of the sender in the reward left of a closed farm.
+    #[payable]
+    pub fn claim_refund(&mut self, farm_id: FarmIdJson) -> Promise {
+        self.assert_running();
+        assert_one_yocto();
+        let sender_id = env::predecessor_account_id();
+        let farm_id = self.internal_unwrap_farm_id(&farm_id);
+        let mut farm = self.internal_unwrap_farm(&farm_id);
+        let contribution = farm.contributions.remove(&sender_id).unwrap_or(0);
+        let refund = mul_div(farm.refundable, contribution, std::cmp::max(farm.total_contributed, 1));
+        require(refund != 0, FarmingError::NothingToRefund);
+        self.farms.insert(&farm_id, &farm);
+        ext_ft::ft_transfer(
+            sender_id.clone(),
+            refund.to_string(),
+            None,
+            &farm.terms.seed_id,
+            1,
+            GAS_FOR_FT_TRANSFER,
+        )
+        .then(ext_self::callback_claim_refund(
+            sender_id,
+            farm_id,
+            U128(contribution),
+            &env::current_account_id(),
+            0,
+            GAS_FOR_RESOLVE_REWARD,
+        ))
+    }
+
+    /// Sets the share of future claims of the farm which vests, `None`
+    /// pays them at once.
+    pub fn set_farm_vesting(&mut self, farm_id: FarmIdJson, vesting: Option<VestingTerms>) {
+        let farm_id = self.internal_unwrap_farm_id(&farm_id);
+        let mut farm = self.internal_unwrap_farm(&farm_id);
+        require(
+            env::predecessor_account_id() == farm.owner_id,
+            FarmingError::NotFarmOwner,
+        );
+        if let Some(vesting) = &vesting {
+            require(
+                vesting.share_bps as u128 <= BP_DENOMINATOR && vesting.days > 0,
+                FarmingError::InvalidVesting,
+            );
+        }
+        farm.terms.vesting = vesting;
+        self.farms.insert(&farm_id, &farm);
+    }
+
+    /// Pays the vested reward of `seed_id` unlocked so far.
+    #[payable]
+    pub fn claim_vested(&mut self, seed_id: SeedId) -> U128 {
+        self.assert_running();
+        assert_one_yocto();
+        let sender_id = env::predecessor_account_id();
+        let now = env::block_timestamp();
+        let mut farmer = self.internal_unwrap_farmer(&sender_id);
+        let mut schedule = farmer
+            .vesting
+            .get(&seed_id)
+            .unwrap_or_else(|| FarmingError::NothingToClaim.panic());
+        let amount = schedule.claim(now);
+        require(amount != 0, FarmingError::NothingToClaim);
+        if schedule.locked == 0 {
+            farmer.vesting.remove(&seed_id);
+        } else {
+            farmer.vesting.insert(&seed_id, &schedule);
+        }
+        self.farmers.insert(&sender_id, &farmer);
+        self.internal_add_seed_claim(&seed_id, amount, false);
+        self.internal_claim_user_reward(amount, &sender_id, &seed_id, None);
+        amount.into()
+    }
+
+    /// Pays the reward of `seed_id` whose transfer failed before.
+    #[payable]
+    pub fn claim_unpaid_reward(&mut self, seed_id: SeedId) -> U128 {
+        self.assert_running();
+        assert_one_yocto();
+        let sender_id = env::predecessor_account_id();
+        let mut farmer = self.internal_unwrap_farmer(&sender_id);
+        let amount = farmer.unpaid_rewards.remove(&seed_id).unwrap_or(0);
+        require(amount != 0, FarmingError::NothingToClaim);
+        self.farmers.insert(&sender_id, &farmer);
+        self.internal_claim_user_reward(amount, &sender_id, &seed_id, None);
+        amount.into()
+    }
+
+    /// View methods.
+    pub fn get_unpaid_rewards(&self, account_id: AccountId) -> Vec<(SeedId, U128)> {
+        self.farmers
+            .get(&account_id)
+            .map(|farmer| {
+                farmer
+                    .unpaid_rewards
+                    .into_iter()
+                    .map(|(seed_id, amount)| (seed_id, amount.into()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn get_vesting_balances(&self, account_id: AccountId) -> Vec<VestingBalance> {
+        let now = env::block_timestamp();
+        match self.farmers.get(&account_id) {
+            Some(farmer) => farmer
+                .vesting
+                .iter()
+                .map(|(seed_id, schedule)| VestingBalance {
+                    seed_id,
+                    locked: schedule.locked_at(now).into(),
+                    unlocked: schedule.unlocked_at(now).into(),
+                    end_at: schedule.end_at,
+                })
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    pub fn get_farm_contribution(&self, farm_id: FarmIdJson, account_id: AccountId) -> U128 {
+        self.internal_get_farm(&farm_id)
+            .and_then(|farm| farm.contributions.get(&account_id))
+            .unwrap_or(0)
+            .into()
+    }
+
+    /// Registers the farmer with the reward token if needed, paid from
+    /// the storage balance of the farmer, then transfers the reward.
+    #[private]
+    pub fn callback_claim_user_reward(
+        &mut self,
+        sender_id: AccountId,
+        seed_id: SeedId,
+        farm_id: Option<FarmId>,
+        amount: U128,
+    ) {
+        require(env::promise_results_count() == 1, FarmingError::TooManyResults);
+        let is_registered = match env::promise_result(0) {
+            PromiseResult::Successful(val) => serde_json::from_slice::<Option<StorageBalance>>(&val)
+                .map_or(false, |storage_balance| storage_balance.is_some()),
+            _ => false,
+        };
+        let transfer = || {
+            ext_ft::ft_transfer(
+                sender_id.clone(),
+                amount.0.to_string(),
+                None,
+                &seed_id,
+                1,
+                GAS_FOR_FT_TRANSFER,
+            )
+            .then(ext_self::callback_resolve_reward(
+                sender_id.clone(),
+                seed_id.clone(),
+                farm_id,
+                amount,
+                &env::current_account_id(),
+                0,
+                GAS_FOR_RESOLVE_REWARD,
+            ))
+        };
+        if is_registered {
+            transfer();
+            return;
+        }
+
+        let mut farmer = match self.farmers.get(&sender_id) {
+            Some(farmer) => farmer,
+            None => {
+                self.internal_add_unpaid_reward(&sender_id, &seed_id, farm_id, amount.0);
+                return;
+            }
+        };
+        if farmer.storage_balance < FT_STORAGE_DEPOSIT {
+            env::log(
+                format!(
+                    "Reward {} of {} is unpaid, not enough storage balance to register with {}",
+                    amount.0, sender_id, seed_id
+                )
+                .as_bytes(),
+            );
+            self.internal_add_unpaid_reward(&sender_id, &seed_id, farm_id, amount.0);
+            return;
+        }
+        farmer.storage_balance -= FT_STORAGE_DEPOSIT;
+        self.farmers.insert(&sender_id, &farmer);
+        ext_ft::storage_deposit(
+            sender_id.clone(),
+            true,
+            &seed_id,
+            FT_STORAGE_DEPOSIT,
+            GAS_FOR_FT_DEPOSIT,
+        )
+        .then(transfer());
+    }
+
+    /// Gives the contribution back when the refund transfer failed,
+    /// so that it can be claimed again.
+    #[private]
+    pub fn callback_claim_refund(&mut self, sender_id: AccountId, farm_id: FarmId, contribution: U128) {
+        require(env::promise_results_count() == 1, FarmingError::TooManyResults);
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return;
+        }
+        if let Some(mut farm) = self.farms.get(&farm_id) {
+            let prev_contribution = farm.contributions.get(&sender_id).unwrap_or(0);
+            farm.contributions.insert(&sender_id, &(prev_contribution + contribution.0));
+            self.farms.insert(&farm_id, &farm);
+            env::log(format!("Refund of {} in farm {} failed, it can be claimed again", sender_id, farm_id).as_bytes());
+        }
+    }
+
+    /// Keeps the reward of a failed transfer as unpaid.
+    #[private]
+    pub fn callback_resolve_reward(
+        &mut self,
+        sender_id: AccountId,
+        seed_id: SeedId,
+        farm_id: Option<FarmId>,
+        amount: U128,
+    ) {
+        require(env::promise_results_count() == 1, FarmingError::TooManyResults);
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return;
+        }
+        env::log(
+            format!("Reward {} of {} is unpaid, transfer of {} failed", amount.0, sender_id, seed_id)
+                .as_bytes(),
+        );
+        self.internal_add_unpaid_reward(&sender_id, &seed_id, farm_id, amount.0);
+    }
+}
+
+impl Contract {
+    /// Reward of an unregistered farmer goes back into `farm_id`, the
+    /// funders can get it back with `claim_refund` once the farm ended.
+    pub(crate) fn internal_add_unpaid_reward(
+        &mut self,
+        sender_id: &AccountId,
+        seed_id: &SeedId,
+        farm_id: Option<FarmId>,
+        amount: Balance,
+    ) {
+        if let Some(mut farmer) = self.farmers.get(sender_id) {
+            *farmer.unpaid_rewards.entry(seed_id.clone()).or_insert(0) += amount;
+            self.farmers.insert(sender_id, &farmer);
+            return;
+        }
+        match farm_id.and_then(|farm_id| self.farms.get(&farm_id).map(|farm| (farm_id, farm))) {
+            Some((farm_id, mut farm)) => {
+                if farm.status == Status::Ended {
+                    farm.refundable += amount;
+                } else {
+                    farm.amount_of_reward += amount;
+                }
+                farm.amount_of_claimed = farm.amount_of_claimed.saturating_sub(amount);
+                self.farms.insert(&farm_id, &farm);
+                if let Some(mut seed) = self.seeds.get(seed_id) {
+                    seed.total_claimed = seed.total_claimed.saturating_sub(amount);
+                    self.seeds.insert(seed_id, &seed);
+                }
+                env::log(
+                    format!("Reward {} of {} went back into farm {}", amount, sender_id, farm_id).as_bytes(),
+                );
+            }
+            None => env::log(
+                format!("Reward {} of {} in {} is kept by the contract", amount, sender_id, seed_id).as_bytes(),
+            ),
+        }
+    }
+
     pub fn internal_claim_user_reward(
         &mut self,
         amount: Balance,
         sender_id: &AccountId,
         seed_id: &SeedId,
+        farm_id: Option<FarmId>,
     ) -> Promise {
-        ext_ft::ft_transfer(
-            sender_id.clone().try_into().unwrap(),
-            amount.to_string(),
-            None,
-            seed_id,
-            1,
-            GAS_FOR_FT_TRANSFER,
+        ext_ft::storage_balance_of(sender_id.clone(), seed_id, 0, GAS_FOR_FT_VIEW).then(
+            ext_self::callback_claim_user_reward(
+                sender_id.clone(),
+                seed_id.clone(),
+                farm_id,
+                U128(amount),
+                &env::current_account_id(),
+                0,
+                GAS_FOR_CLAIM_CALLBACK,
+            ),
         )
     }
 
@@ -27,20 +443,64 @@ impl Contract {
         seed_id: AccountId,
         farm_id: FarmId,
         amount: Balance,
-    ) {
-        if let Some(mut farm) = self.farms.get(&farm_id) {
-            assert!(
-                sender_id == farm.owner_id,
-                "You are not farm owner"
-            );
-            assert!(
-                seed_id == farm.terms.seed_id,
-                "You added wrong seed"
-            );
-            farm.add_reward(&amount);
-            self.farms.insert(&farm_id, &farm);
-        } else {
-            env::panic(b"Farm not found");
+    ) -> Result<(), FarmingError> {
+        self.internal_fund_farm(sender_id, seed_id, farm_id, amount, None)
+    }
+
+    /// Funds the farm like `internal_add_reward_to_farm`, the reward is
+    /// released linearly from `start_at` to `end_at` and kept in
+    /// `Farm::stream_reward`.
+    pub fn internal_add_stream_to_farm(
+        &mut self,
+        sender_id: AccountId,
+        seed_id: AccountId,
+        farm_id: FarmId,
+        amount: Balance,
+        start_at: Timestamp,
+        end_at: Timestamp,
+    ) -> Result<(), FarmingError> {
+        let now = env::block_timestamp();
+        if start_at < now || end_at <= start_at || amount == 0 {
+            return Err(FarmingError::InvalidStream);
+        }
+        let streams = self.farms.get(&farm_id).ok_or(FarmingError::FarmNotFound)?.streams;
+        if streams.iter().filter(|stream| stream.end_at > now).count() >= MAX_STREAMS {
+            return Err(FarmingError::TooManyStreams);
+        }
+        self.internal_fund_farm(sender_id, seed_id, farm_id, amount, Some(Stream { start_at, end_at, amount }))
+    }
+
+    fn internal_fund_farm(
+        &mut self,
+        sender_id: AccountId,
+        seed_id: AccountId,
+        farm_id: FarmId,
+        amount: Balance,
+        stream: Option<Stream>,
+    ) -> Result<(), FarmingError> {
+        let mut farm = self.farms.get(&farm_id).ok_or(FarmingError::FarmNotFound)?;
+        if !farm.can_be_funded_by(&sender_id) {
+            return Err(FarmingError::FundingNotAllowed);
+        }
+        if seed_id != farm.terms.seed_id {
+            return Err(FarmingError::WrongSeed);
+        }
+        if farm.status == Status::Ended {
+            return Err(FarmingError::FarmEnded);
+        }
+        if farm.closing_at.is_some() {
+            return Err(FarmingError::FarmClosing);
+        }
+        let activated = farm.status == Status::Created;
+        match stream {
+            Some(stream) => farm.add_stream(stream),
+            None => farm.add_reward(&amount),
         }
+        let contribution = farm.contributions.get(&sender_id).unwrap_or(0);
+        farm.contributions.insert(&sender_id, &(contribution + amount));
+        farm.total_contributed += amount;
+        self.farms.insert(&farm_id, &farm);
+        self.internal_add_seed_deposit(&seed_id, amount, activated);
+        Ok(())
     }
 }
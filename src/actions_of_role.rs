@@ -0,0 +1,70 @@
+use crate::events::Event;
+use crate::role::{Role, RoleSet};
+use crate::*;
+use near_sdk::json_types::ValidAccountId;
+use near_sdk::{env, near_bindgen, AccountId};
+
+#[near_bindgen]
+impl Contract {
+    pub fn grant_role(&mut self, account_id: ValidAccountId, role: Role) {
+        self.assert_role(Role::Owner);
+        let account_id: AccountId = account_id.into();
+        let mut role_set = self.roles.get(&account_id).unwrap_or_default();
+        role_set.insert(role);
+        self.roles.insert(&account_id, &role_set);
+        Event::RoleGranted {
+            account_id: &account_id,
+            role,
+            by: &env::predecessor_account_id(),
+        }
+        .emit();
+    }
+
+    pub fn revoke_role(&mut self, account_id: ValidAccountId, role: Role) {
+        self.assert_role(Role::Owner);
+        let account_id: AccountId = account_id.into();
+        if let Some(mut role_set) = self.roles.get(&account_id) {
+            role_set.remove(role);
+            if role_set.is_empty() {
+                self.roles.remove(&account_id);
+            } else {
+                self.roles.insert(&account_id, &role_set);
+            }
+            Event::RoleRevoked {
+                account_id: &account_id,
+                role,
+                by: &env::predecessor_account_id(),
+            }
+            .emit();
+        }
+    }
+
+    /// View methods.
+    pub fn get_roles(&self, account_id: AccountId) -> Vec<Role> {
+        if account_id == self.owner_id {
+            return vec![Role::Owner];
+        }
+        self.roles
+            .get(&account_id)
+            .map(|role_set| role_set.roles())
+            .unwrap_or_default()
+    }
+
+    pub fn has_role(&self, account_id: AccountId, role: Role) -> bool {
+        if account_id == self.owner_id {
+            return true;
+        }
+        self.roles.get(&account_id).map_or(false, |role_set: RoleSet| {
+            role_set.contains(Role::Owner) || role_set.contains(role)
+        })
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_role(&self, role: Role) {
+        require(
+            self.has_role(env::predecessor_account_id(), role),
+            FarmingError::NotAllowed,
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use crate::errors::{require, FarmingError};
+use crate::role::Role;
+use crate::seed::{Seed, SeedInfo};
+use crate::utils::{ext_ft, ext_self, FT_STORAGE_DEPOSIT, GAS_FOR_FT_VIEW, XCC_GAS};
+use crate::*;
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
+use near_sdk::json_types::ValidAccountId;
+use near_sdk::{env, near_bindgen, serde_json, Balance, Promise, PromiseResult};
+
+#[near_bindgen]
+impl Contract {
+    /// Allows farms to be rewarded with `token_id`, the attached deposit
+    /// registers this contract with the token, the rest is refunded.
+    /// The seed of the token is created and its metadata fetched.
+    #[payable]
+    pub fn approve_reward_token(&mut self, token_id: ValidAccountId) -> Promise {
+        self.assert_role(Role::Owner);
+        let deposit = env::attached_deposit();
+        require(deposit >= FT_STORAGE_DEPOSIT, FarmingError::NotEnoughDeposit);
+        let token_id: AccountId = token_id.into();
+        self.reward_tokens.insert(&token_id);
+        if self.seeds.get(&token_id).is_none() {
+            self.seeds.insert(&token_id, &Seed::new(token_id.clone()));
+        }
+        if deposit > FT_STORAGE_DEPOSIT {
+            Promise::new(env::predecessor_account_id()).transfer(deposit - FT_STORAGE_DEPOSIT);
+        }
+        env::log(format!("Reward token {} approved", token_id).as_bytes());
+        ext_ft::ft_metadata(&token_id, 0, GAS_FOR_FT_VIEW).then(ext_self::callback_set_seed_metadata(
+            token_id.clone(),
+            &env::current_account_id(),
+            0,
+            XCC_GAS,
+        ));
+        ext_ft::storage_deposit(
+            env::current_account_id(),
+            true,
+            &token_id,
+            FT_STORAGE_DEPOSIT,
+            GAS_FOR_FT_DEPOSIT,
+        )
+    }
+
+    /// Existing farms of the token keep running, no new farm can use it.
+    pub fn remove_reward_token(&mut self, token_id: ValidAccountId) {
+        self.assert_role(Role::Owner);
+        let token_id: AccountId = token_id.into();
+        self.reward_tokens.remove(&token_id);
+        env::log(format!("Reward token {} removed", token_id).as_bytes());
+    }
+
+    /// View methods.
+    pub fn get_reward_tokens(&self) -> Vec<SeedId> {
+        self.reward_tokens.to_vec()
+    }
+
+    pub fn is_reward_token(&self, token_id: ValidAccountId) -> bool {
+        let token_id: AccountId = token_id.into();
+        self.reward_tokens.contains(&token_id)
+    }
+
+    pub fn list_seeds_info(&self, from_index: u64, limit: u64) -> Vec<SeedInfo> {
+        let values = self.seeds.values_as_vector();
+
+        (from_index..std::cmp::min(from_index + limit, values.len()))
+            .map(|index| SeedInfo::from(&values.get(index).unwrap()))
+            .collect()
+    }
+
+    /// Keeps symbol and decimals of the seed, a failed call leaves them empty.
+    #[private]
+    pub fn callback_set_seed_metadata(&mut self, seed_id: SeedId) {
+        require(env::promise_results_count() == 1, FarmingError::TooManyResults);
+        if let PromiseResult::Successful(val) = env::promise_result(0) {
+            if let Ok(metadata) = serde_json::from_slice::<FungibleTokenMetadata>(&val) {
+                if let Some(mut seed) = self.seeds.get(&seed_id) {
+                    seed.symbol = Some(metadata.symbol);
+                    seed.decimals = Some(metadata.decimals);
+                    self.seeds.insert(&seed_id, &seed);
+                }
+            }
+        }
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_add_seed_deposit(&mut self, seed_id: &SeedId, amount: Balance, activated: bool) {
+        if let Some(mut seed) = self.seeds.get(seed_id) {
+            seed.total_deposited += amount;
+            if activated {
+                seed.active_farms += 1;
+            }
+            self.seeds.insert(seed_id, &seed);
+        }
+    }
+
+    pub(crate) fn internal_add_seed_claim(&mut self, seed_id: &SeedId, amount: Balance, ended: bool) {
+        if let Some(mut seed) = self.seeds.get(seed_id) {
+            seed.total_claimed += amount;
+            if ended {
+                seed.active_farms = seed.active_farms.saturating_sub(1);
+            }
+            self.seeds.insert(seed_id, &seed);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use near_sdk::env;
+use std::fmt;
+
+/// Errors of the contract. Panic messages are `{code}: {message}`,
+/// codes are stable and can be matched by clients.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FarmingError {
+    NotAllowed,
+    ContractPaused,
+    NotEnoughDeposit,
+    NftContractNotWhitelisted,
+    TooManyFarms,
+    NotNftContractAdmin,
+    NotNftsOwner,
+    NotContractOwner,
+    InvalidStakeUnit,
+    NoAcceptedTokens,
+    WrongFarmId,
+    FarmNotFound,
+    FarmNotRunning,
+    FarmNotStarted,
+    FarmEnded,
+    NotFarmOwner,
+    FundingNotAllowed,
+    FarmClosing,
+    NothingToRefund,
+    WrongSeed,
+    WrongFarmKind,
+    TokenNotAccepted,
+    ApprovalNotSupported,
+    AlreadyStaked,
+    AlreadyBoosted,
+    RewardAlmostOver,
+    NothingToClaim,
+    FarmerNotRegistered,
+    NotStaked,
+    NftNotFound,
+    NotNftOwner,
+    NftLocked,
+    InvalidAmount,
+    NotEnoughStaked,
+    WrongTokenAmounts,
+    WrongContractTokenId,
+    RateInPast,
+    RateOutOfOrder,
+    InvalidStream,
+    TooManyStreams,
+    InvalidVesting,
+    InvalidLoyaltyCurve,
+    InvalidNftSets,
+    FarmFull,
+    WalletCapReached,
+    TooManyNfts,
+    NotEnoughStorageDeposit,
+    NotEnoughStorageBalance,
+    FarmerNotEmpty,
+    RewardTokenNotAllowed,
+    MissingFarmId,
+    WrongMessage,
+    NotCrossContractCall,
+    OwnerNotSigner,
+    TooManyResults,
+    WrongValReceived,
+    CallFailed,
+    MigrationPending,
+}
+
+impl FarmingError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            FarmingError::NotAllowed => "ERR_NOT_ALLOWED",
+            FarmingError::ContractPaused => "ERR_CONTRACT_PAUSED",
+            FarmingError::NotEnoughDeposit => "ERR_NOT_ENOUGH_DEPOSIT",
+            FarmingError::NftContractNotWhitelisted => "ERR_NFT_CONTRACT_NOT_WHITELISTED",
+            FarmingError::TooManyFarms => "ERR_TOO_MANY_FARMS",
+            FarmingError::NotNftContractAdmin => "ERR_NOT_NFT_CONTRACT_ADMIN",
+            FarmingError::NotNftsOwner => "ERR_NOT_NFTS_OWNERS",
+            FarmingError::NotContractOwner => "ERR_NOT_CONTRACT_OWNER",
+            FarmingError::InvalidStakeUnit => "ERR_INVALID_STAKE_UNIT",
+            FarmingError::NoAcceptedTokens => "ERR_NO_ACCEPTED_TOKENS",
+            FarmingError::WrongFarmId => "ERR_WRONG_FARM_ID",
+            FarmingError::FarmNotFound => "ERR_FARM_NOT_FOUND",
+            FarmingError::FarmNotRunning => "ERR_FARM_NOT_RUNNING",
+            FarmingError::FarmNotStarted => "ERR_FARM_NOT_STARTED",
+            FarmingError::FarmEnded => "ERR_FARM_ENDED",
+            FarmingError::NotFarmOwner => "ERR_NOT_FARM_OWNER",
+            FarmingError::FundingNotAllowed => "ERR_FUNDING_NOT_ALLOWED",
+            FarmingError::FarmClosing => "ERR_FARM_CLOSING",
+            FarmingError::NothingToRefund => "ERR_NOTHING_TO_REFUND",
+            FarmingError::WrongSeed => "ERR_WRONG_SEED",
+            FarmingError::WrongFarmKind => "ERR_WRONG_FARM_KIND",
+            FarmingError::TokenNotAccepted => "ERR_TOKEN_NOT_ACCEPTED",
+            FarmingError::ApprovalNotSupported => "ERR_APPROVAL_NOT_SUPPORTED",
+            FarmingError::AlreadyStaked => "ERR_ALREADY_STAKED",
+            FarmingError::AlreadyBoosted => "ERR_ALREADY_BOOSTED",
+            FarmingError::RewardAlmostOver => "ERR_REWARD_ALMOST_OVER",
+            FarmingError::NothingToClaim => "ERR_NOTHING_TO_CLAIM",
+            FarmingError::FarmerNotRegistered => "ERR_FARMER_NOT_REGISTERED",
+            FarmingError::NotStaked => "ERR_NOT_STAKED",
+            FarmingError::NftNotFound => "ERR_NFT_NOT_FOUND",
+            FarmingError::NotNftOwner => "ERR_NOT_NFT_OWNER",
+            FarmingError::NftLocked => "ERR_NFT_LOCKED",
+            FarmingError::InvalidAmount => "ERR_INVALID_AMOUNT",
+            FarmingError::NotEnoughStaked => "ERR_NOT_ENOUGH_STAKED",
+            FarmingError::WrongTokenAmounts => "ERR_WRONG_TOKEN_AMOUNTS",
+            FarmingError::WrongContractTokenId => "ERR_WRONG_CONTRACT_TOKEN_ID",
+            FarmingError::RateInPast => "ERR_RATE_IN_PAST",
+            FarmingError::RateOutOfOrder => "ERR_RATE_OUT_OF_ORDER",
+            FarmingError::InvalidStream => "ERR_INVALID_STREAM",
+            FarmingError::TooManyStreams => "ERR_TOO_MANY_STREAMS",
+            FarmingError::InvalidVesting => "ERR_INVALID_VESTING",
+            FarmingError::InvalidLoyaltyCurve => "ERR_INVALID_LOYALTY_CURVE",
+            FarmingError::InvalidNftSets => "ERR_INVALID_NFT_SETS",
+            FarmingError::FarmFull => "ERR_FARM_FULL",
+            FarmingError::WalletCapReached => "ERR_WALLET_CAP_REACHED",
+            FarmingError::TooManyNfts => "ERR_TOO_MANY_NFTS",
+            FarmingError::NotEnoughStorageDeposit => "ERR_NOT_ENOUGH_STORAGE_DEPOSIT",
+            FarmingError::NotEnoughStorageBalance => "ERR_NOT_ENOUGH_STORAGE_BALANCE",
+            FarmingError::FarmerNotEmpty => "ERR_FARMER_NOT_EMPTY",
+            FarmingError::RewardTokenNotAllowed => "ERR_REWARD_TOKEN_NOT_ALLOWED",
+            FarmingError::MissingFarmId => "ERR_MISSING_FARM_ID",
+            FarmingError::WrongMessage => "ERR_WRONG_MESSAGE",
+            FarmingError::NotCrossContractCall => "ERR_NOT_CROSS_CONTRACT_CALL",
+            FarmingError::OwnerNotSigner => "ERR_OWNER_NOT_SIGNER",
+            FarmingError::TooManyResults => "ERR_TOO_MANY_RESULTS",
+            FarmingError::WrongValReceived => "ERR_WRONG_VAL_RECEIVED",
+            FarmingError::CallFailed => "ERR_CALL_FAILED",
+            FarmingError::MigrationPending => "ERR_MIGRATION_PENDING",
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            FarmingError::NotAllowed => "You are not allowed to do this",
+            FarmingError::ContractPaused => "Contract is paused",
+            FarmingError::NotEnoughDeposit => "You need attach more yocto",
+            FarmingError::NftContractNotWhitelisted => "We are not connected with this NFT contract",
+            FarmingError::TooManyFarms => "This NFT contract reached its farms limit",
+            FarmingError::NotNftContractAdmin => "You are not admin of this NFT contract",
+            FarmingError::NotNftsOwner => "You are not creator of these NFTs",
+            FarmingError::NotContractOwner => "You are not owner of this NFT contract",
+            FarmingError::InvalidStakeUnit => "Stake unit must be positive",
+            FarmingError::NoAcceptedTokens => "No accepted tokens",
+            FarmingError::WrongFarmId => "Farm id must be an index or seed_id#index",
+            FarmingError::FarmNotFound => "Farm not found",
+            FarmingError::FarmNotRunning => "Farm is not running",
+            FarmingError::FarmNotStarted => "Farm is not started",
+            FarmingError::FarmEnded => "This farm is ended",
+            FarmingError::NotFarmOwner => "You are not farm owner",
+            FarmingError::FundingNotAllowed => "You are not allowed to fund this farm",
+            FarmingError::FarmClosing => "Farm is closing, stakers can still claim",
+            FarmingError::NothingToRefund => "Nothing to refund",
+            FarmingError::WrongSeed => "You added wrong seed",
+            FarmingError::WrongFarmKind => "This farm does not accept this kind of token",
+            FarmingError::TokenNotAccepted => "This farm is not accept your token",
+            FarmingError::ApprovalNotSupported => "This farm does not support staking by approval",
+            FarmingError::AlreadyStaked => "This NFT is already staked",
+            FarmingError::AlreadyBoosted => "You already boosted this farm",
+            FarmingError::RewardAlmostOver => "Farm's reward is almost over. Please claim your rewards as soon as possible",
+            FarmingError::NothingToClaim => "Not time for claiming reward",
+            FarmingError::FarmerNotRegistered => "Farmer is not registered",
+            FarmingError::NotStaked => "You did not stake in this farm",
+            FarmingError::NftNotFound => "Not found this NFT in farm",
+            FarmingError::NotNftOwner => "You are not the owner of this NFT",
+            FarmingError::NftLocked => "Not time for withdraw",
+            FarmingError::InvalidAmount => "Amount must be positive",
+            FarmingError::NotEnoughStaked => "Not enough staked balance",
+            FarmingError::WrongTokenAmounts => "Token ids and amounts do not match",
+            FarmingError::WrongContractTokenId => "Wrong contract token id",
+            FarmingError::RateInPast => "Rate can not change in the past",
+            FarmingError::RateOutOfOrder => "Rate change must be after the last one",
+            FarmingError::InvalidStream => "Stream must end after it starts, not in the past",
+            FarmingError::TooManyStreams => "Too many streams in this farm",
+            FarmingError::InvalidVesting => "Vesting share must be at most 100% over at least one day",
+            FarmingError::InvalidLoyaltyCurve => "Loyalty curve needs at most 8 tiers sorted by days, growing bonuses up to 100%",
+            FarmingError::InvalidNftSets => "NFT sets need unique names and non empty rules",
+            FarmingError::FarmFull => "The farm can not take more NFTs",
+            FarmingError::WalletCapReached => "The farmer can not stake more NFTs in this farm",
+            FarmingError::TooManyNfts => "Too many approved NFTs to verify in one call",
+            FarmingError::NotEnoughStorageDeposit => "Not deposit enough storage",
+            FarmingError::NotEnoughStorageBalance => "Not enough storage balance",
+            FarmingError::FarmerNotEmpty => "Unstake and claim all rewards before unregistering",
+            FarmingError::RewardTokenNotAllowed => "This token is not allowed as reward",
+            FarmingError::MissingFarmId => "Not found farm_id args",
+            FarmingError::WrongMessage => "Wrong format",
+            FarmingError::NotCrossContractCall => "Should only be called via cross-contract call",
+            FarmingError::OwnerNotSigner => "Owner_id should be signer_id",
+            FarmingError::TooManyResults => "Wrong number of promise results",
+            FarmingError::WrongValReceived => "Wrong value received",
+            FarmingError::CallFailed => "Cross-contract call failed",
+            FarmingError::MigrationPending => "Farms of the first release are not migrated yet",
+        }
+    }
+
+    pub fn panic(&self) -> ! {
+        env::panic(self.to_string().as_bytes())
+    }
+}
+
+impl fmt::Display for FarmingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.code(), self.message())
+    }
+}
+
+pub(crate) fn require(condition: bool, error: FarmingError) {
+    if !condition {
+        error.panic()
+    }
+}
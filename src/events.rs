@@ -0,0 +1,71 @@
+use crate::role::Role;
+use near_sdk::serde::Serialize;
+use near_sdk::json_types::U128;
+use near_sdk::{env, serde_json, AccountId};
+
+pub const EVENT_STANDARD: &str = "nft_staking";
+pub const EVENT_VERSION: &str = "1.0.0";
+
+/// Events logged as `EVENT_JSON:{"standard":..,"version":..,"event":..,"data":{..}}`.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum Event<'a> {
+    RoleGranted {
+        account_id: &'a AccountId,
+        role: Role,
+        by: &'a AccountId,
+    },
+    RoleRevoked {
+        account_id: &'a AccountId,
+        role: Role,
+        by: &'a AccountId,
+    },
+    /// Tokens sent with a msg which could not be handled, `ft_on_transfer`
+    /// returned them as unused.
+    FtTransferRefunded {
+        sender_id: &'a AccountId,
+        token_id: &'a AccountId,
+        amount: U128,
+        code: &'static str,
+        message: &'static str,
+    },
+    /// NFT sent to a farm which could not take it, `nft_on_transfer`
+    /// returned it to its owner.
+    NftTransferRefunded {
+        owner_id: &'a AccountId,
+        nft_contract_id: &'a AccountId,
+        token_id: &'a str,
+        code: &'static str,
+        message: &'static str,
+    },
+    /// NFTs could not be added to the farm by `add_accepted_nfts`,
+    /// the deposit was returned to the farm owner.
+    AcceptedNftsRefunded {
+        farm_id: &'a str,
+        owner_id: &'a AccountId,
+        deposit: U128,
+        code: &'static str,
+        message: &'static str,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct EventLog<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+impl Event<'_> {
+    pub fn emit(&self) {
+        let log = EventLog {
+            standard: EVENT_STANDARD,
+            version: EVENT_VERSION,
+            event: self,
+        };
+        env::log(format!("EVENT_JSON:{}", serde_json::to_string(&log).unwrap()).as_bytes());
+    }
+}
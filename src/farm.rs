@@ -5,14 +5,48 @@ use near_sdk::json_types::{U64, U128};
 use near_sdk::serde::{Deserialize, Serialize};
 use std::collections::{HashSet};
 use crate::{SeedId, StorageKeys};
+use crate::farmer::StakingInfo;
+use crate::utils::mul_div;
+use crate::errors::FarmingError;
 
-pub(crate) type FarmId = String;
+/// Global index of a farm, farms created earlier have a lower id.
+pub(crate) type FarmId = u64;
+/// `FarmId` in JSON, also accepts a legacy `seed_id#index`.
+pub(crate) type FarmIdJson = String;
 
 #[derive(BorshSerialize, BorshDeserialize, Clone, PartialEq)]
 pub enum Status {
     Created, Running, Ended
 }
 
+pub const BP_DENOMINATOR: u128 = 10_000;
+
+/// What a farm accepts as stake.
+/// `Fungible` farms take `ft_contract_id` tokens; `stake_unit` of them
+/// earn `reward_per_session` per session, like one NFT does.
+/// `Hybrid` farms are `Fungible` farms where each farmer can also stake
+/// one NFT of a whitelisted contract to boost its yield.
+/// `MultiToken` farms take NEP-245 tokens listed in `accepted_nfts`,
+/// each unit earning like one NFT.
+#[derive(BorshSerialize, BorshDeserialize, Clone, PartialEq)]
+pub enum FarmKind {
+    NonFungible,
+    Fungible { ft_contract_id: AccountId, stake_unit: Balance },
+    Hybrid { ft_contract_id: AccountId, stake_unit: Balance },
+    MultiToken { mt_contract_id: AccountId },
+}
+
+impl From<&FarmKind> for String {
+    fn from(kind: &FarmKind) -> Self {
+        match *kind {
+            FarmKind::NonFungible => { String::from("NonFungible") },
+            FarmKind::Fungible { .. } => { String::from("Fungible") },
+            FarmKind::Hybrid { .. } => { String::from("Hybrid") },
+            FarmKind::MultiToken { .. } => { String::from("MultiToken") }
+        }
+    }
+}
+
 pub type ContractNFTTokenId = String;
 pub type NFTTokenId = String;
 
@@ -29,7 +63,9 @@ impl From<&Status> for String {
 #[derive(BorshSerialize, BorshDeserialize, Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct StakedInfo {
     pub owner_id: AccountId,
-    pub staked_at: Timestamp
+    pub staked_at: Timestamp,
+    /// Set when the NFT is staked by approval and stays with its owner.
+    pub approval_id: Option<u64>
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Clone)]
@@ -38,6 +74,54 @@ pub struct Terms {
     pub start_at: Timestamp,
     pub reward_per_session: Balance,
     pub session_interval: Timestamp,
+    /// Later rates as `(effective_at, reward_per_session)`, sorted by time.
+    pub rate_history: Vec<(Timestamp, Balance)>,
+    pub vesting: Option<VestingTerms>,
+    /// Bonuses of NFTs staked without interruption, sorted by days.
+    pub loyalty_curve: Vec<LoyaltyTier>,
+    /// Replaced curves as `(replaced_at, loyalty_curve)`, sorted by time,
+    /// the reward accrued before a change keeps the curve of that time.
+    pub loyalty_history: Vec<(Timestamp, Vec<LoyaltyTier>)>,
+    /// Sets of NFTs earning a bonus when a farmer stakes all of them.
+    pub nft_sets: Vec<NftSet>,
+    /// Most NFTs staked in the farm at once, unlimited if `None`.
+    pub max_total_staked: Option<u64>,
+    /// Most NFTs staked by one farmer at once, unlimited if `None`.
+    pub max_per_wallet: Option<u64>,
+}
+
+impl Terms {
+    pub fn rate_at(&self, timestamp: Timestamp) -> Balance {
+        self.rate_history
+            .iter()
+            .rev()
+            .find(|(effective_at, _)| *effective_at <= timestamp)
+            .map_or(self.reward_per_session, |(_, rate)| *rate)
+    }
+
+    /// Reward of `weight` units over `[from, to)`, each rate counted
+    /// only for the time it was effective.
+    pub fn reward_between(&self, weight: Balance, from: Timestamp, to: Timestamp) -> Balance {
+        let session_interval = std::cmp::max(self.session_interval, 1) as u128;
+        let segment_reward = |rate: Balance, duration: Timestamp| {
+            mul_div(weight.saturating_mul(rate), duration as u128, session_interval)
+        };
+        let mut reward: Balance = 0;
+        let mut segment_start = from;
+        let mut rate = self.rate_at(from);
+        for (effective_at, next_rate) in self.rate_history.iter() {
+            if *effective_at <= from {
+                continue;
+            }
+            if *effective_at >= to {
+                break;
+            }
+            reward = reward.saturating_add(segment_reward(rate, *effective_at - segment_start));
+            segment_start = *effective_at;
+            rate = *next_rate;
+        }
+        reward.saturating_add(segment_reward(rate, to - segment_start))
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -47,6 +131,10 @@ pub struct TermsJson {
     pub start_at: U64,
     pub reward_per_session: U128,
     pub session_interval: U64,
+    #[serde(default)]
+    pub max_total_staked: Option<U64>,
+    #[serde(default)]
+    pub max_per_wallet: Option<U64>,
 }
 
 impl From<TermsJson> for Terms {
@@ -56,51 +144,558 @@ impl From<TermsJson> for Terms {
             start_at: terms.start_at.into(),
             reward_per_session: terms.reward_per_session.into(),
             session_interval: terms.session_interval.into(),
+            rate_history: vec![],
+            vesting: None,
+            loyalty_curve: vec![],
+            loyalty_history: vec![],
+            nft_sets: vec![],
+            max_total_staked: terms.max_total_staked.map(u64::from),
+            max_per_wallet: terms.max_per_wallet.map(u64::from),
         }
     }
 }
 
+/// Share of each claim, in basis points, which is not paid at once
+/// but vests linearly over `days`.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VestingTerms {
+    pub share_bps: u32,
+    pub days: u32,
+}
+
+/// Bonus on `reward_per_session` of a NFT staked for at least `days`,
+/// in basis points.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LoyaltyTier {
+    pub days: u32,
+    pub bonus_bps: u32,
+}
+
+pub const MAX_LOYALTY_TIERS: usize = 8;
+pub const MAX_LOYALTY_BONUS_BPS: u32 = 10_000;
+
+/// NFTs a farmer has to stake to complete a set.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum SetRule {
+    /// Every accepted NFT whose id starts with the prefix.
+    AllWithPrefix(String),
+    /// One NFT whose id starts with each prefix, e.g. one of each tier.
+    OneOfEach(Vec<String>),
+}
+
+/// Bonus on `reward_per_session` of a farmer who completed the set,
+/// in basis points.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftSet {
+    pub name: String,
+    pub rule: SetRule,
+    pub bonus_bps: u32,
+}
+
+pub const MAX_NFT_SETS: usize = 16;
+
+/// Reward released linearly over `[start_at, end_at)`, shared by
+/// the weight staked at each moment.
+#[derive(BorshSerialize, BorshDeserialize, Clone, PartialEq)]
+pub struct Stream {
+    pub start_at: Timestamp,
+    pub end_at: Timestamp,
+    pub amount: Balance,
+}
+
+impl Stream {
+    /// Part of `amount` released until `timestamp`.
+    pub fn released_at(&self, timestamp: Timestamp) -> Balance {
+        if timestamp <= self.start_at {
+            0
+        } else if timestamp >= self.end_at {
+            self.amount
+        } else {
+            mul_div(
+                self.amount,
+                (timestamp - self.start_at) as u128,
+                (self.end_at - self.start_at) as u128,
+            )
+        }
+    }
+}
+
+pub const MAX_STREAMS: usize = 16;
+/// Time stakers have to claim after a farm with stake is closed.
+pub const CLOSE_NOTICE: Timestamp = 7 * 86_400 * 1_000_000_000;
+/// Scale of `Farm::reward_per_weight`.
+pub const REWARD_PER_WEIGHT_PRECISION: u128 = 1_000_000;
+
+/// Who can add reward to a farm, its owner always can.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum FundingPolicy {
+    OwnerOnly,
+    AllowList(HashSet<AccountId>),
+    Anyone,
+}
+
 #[derive(BorshSerialize, BorshDeserialize)]
 pub struct Farm {
     pub owner_id: AccountId,
+    pub kind: FarmKind,
     pub terms: Terms,
     pub status: Status,
     pub amount_of_reward: Balance,
     pub amount_of_claimed: Balance,
     pub nft_contract_id: AccountId,
     pub staked_nfts: UnorderedMap<NFTTokenId, StakedInfo>,
-    pub accepted_nfts: HashSet<NFTTokenId>
+    pub accepted_nfts: HashSet<NFTTokenId>,
+    pub total_staked: Balance,
+    pub funding_policy: FundingPolicy,
+    /// Reward deposited by each funder, shares of `refundable`.
+    pub contributions: UnorderedMap<AccountId, Balance>,
+    pub total_contributed: Balance,
+    /// Reward left when the farm was closed, refunded pro-rata to funders.
+    pub refundable: Balance,
+    /// Streams not fully released yet, their reward is in `stream_reward`.
+    pub streams: Vec<Stream>,
+    /// Reward of the streams not paid yet, kept apart from `amount_of_reward`
+    /// so that the session reward can not use it.
+    pub stream_reward: Balance,
+    /// Stream reward released per unit of weight since the farm was
+    /// created, scaled by `REWARD_PER_WEIGHT_PRECISION`.
+    pub reward_per_weight: u128,
+    /// Sum of the `staking_weight` of all farmers.
+    pub stream_weight: Balance,
+    pub streams_updated_at: Timestamp,
+    /// Set by `close_farm` on a farm with stake, which ends at this time.
+    pub closing_at: Option<Timestamp>,
+    /// Accepted NFTs of each `AllWithPrefix` set of `terms.nft_sets`.
+    pub set_sizes: Vec<u64>,
+    /// Changed with the sets or the accepted NFTs, farmers recompute their
+    /// completed sets when it differs from theirs.
+    pub sets_version: u64,
+    pub sets_updated_at: Timestamp,
 }
 
 impl Farm {
     pub fn new(
         owner_id: AccountId,
         farm_id: FarmId,
+        kind: FarmKind,
         terms: Terms,
         nft_contract_id: AccountId,
         accepted_nfts: HashSet<NFTTokenId>
     ) -> Self {
         Self {
             owner_id,
+            kind,
             terms,
             status: Status::Created,
             amount_of_claimed: 0,
             amount_of_reward: 0,
             nft_contract_id,
             staked_nfts: UnorderedMap::new(StorageKeys::StakedNFTs {
-                farm_id: farm_id.clone(),
+                farm_id,
+            }),
+            accepted_nfts,
+            total_staked: 0,
+            funding_policy: FundingPolicy::OwnerOnly,
+            contributions: UnorderedMap::new(StorageKeys::FarmContributions { farm_id }),
+            total_contributed: 0,
+            refundable: 0,
+            streams: vec![],
+            stream_reward: 0,
+            reward_per_weight: 0,
+            stream_weight: 0,
+            streams_updated_at: 0,
+            closing_at: None,
+            set_sizes: vec![],
+            sets_version: 0,
+            sets_updated_at: 0,
+        }
+    }
+
+    pub fn is_hybrid(&self) -> bool {
+        matches!(self.kind, FarmKind::Hybrid { .. })
+    }
+
+    pub fn ft_contract_id(&self) -> Option<&AccountId> {
+        match &self.kind {
+            FarmKind::Fungible { ft_contract_id, .. }
+            | FarmKind::Hybrid { ft_contract_id, .. } => Some(ft_contract_id),
+            _ => None,
+        }
+    }
+
+    pub fn mt_contract_id(&self) -> Option<&AccountId> {
+        match &self.kind {
+            FarmKind::MultiToken { mt_contract_id } => Some(mt_contract_id),
+            _ => None,
+        }
+    }
+
+    /// Token ids of `staking_info` which are staked by approval.
+    pub fn approved_nfts_of(&self, staking_info: &StakingInfo) -> Vec<NFTTokenId> {
+        staking_info
+            .token_balances
+            .keys()
+            .filter(|token_id| {
+                self.staked_nfts
+                    .get(token_id)
+                    .map_or(false, |token| token.approval_id.is_some())
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Number of units earning `reward_per_session` in the whole farm,
+    /// with hybrid boosts.
+    pub fn total_weight(&self) -> Balance {
+        self.stream_weight
+    }
+
+    /// Number of units earning `reward_per_session` for this staking.
+    pub(crate) fn staking_weight(&self, staking_info: &StakingInfo) -> Balance {
+        match &self.kind {
+            FarmKind::NonFungible | FarmKind::MultiToken { .. } => staking_info.amount,
+            FarmKind::Fungible { stake_unit, .. } => staking_info.amount / stake_unit,
+            FarmKind::Hybrid { stake_unit, .. } => {
+                mul_div(
+                    staking_info.amount / stake_unit,
+                    BP_DENOMINATOR + staking_info.boost_bps as u128,
+                    BP_DENOMINATOR,
+                )
+            }
+        }
+    }
+
+    fn reward_per_weight_at(&self, now: Timestamp) -> u128 {
+        if self.stream_weight == 0 || now <= self.streams_updated_at {
+            return self.reward_per_weight;
+        }
+        let released: Balance = self
+            .streams
+            .iter()
+            .map(|stream| stream.released_at(now) - stream.released_at(self.streams_updated_at))
+            .sum();
+        self.reward_per_weight + mul_div(released, REWARD_PER_WEIGHT_PRECISION, self.stream_weight)
+    }
+
+    /// Shares the stream reward released until `now`, reward released while
+    /// nothing is staked stays in the farm.
+    pub fn update_streams(&mut self, now: Timestamp) {
+        self.reward_per_weight = self.reward_per_weight_at(now);
+        self.streams_updated_at = std::cmp::max(self.streams_updated_at, now);
+        self.streams.retain(|stream| stream.end_at > now);
+    }
+
+    /// Stream reward earned by `staking_info` and not moved to its
+    /// `pending_stream_reward` yet.
+    pub fn stream_reward_of(&self, staking_info: &StakingInfo, now: Timestamp) -> Balance {
+        mul_div(
+            self.staking_weight(staking_info),
+            self.reward_per_weight_at(now) - staking_info.reward_per_weight_paid,
+            REWARD_PER_WEIGHT_PRECISION,
+        )
+    }
+
+    /// Moves the session and stream reward of `staking_info` into its
+    /// `pending_reward` and `pending_stream_reward` and takes its weight out,
+    /// to be called before its weight or bonuses change and followed by
+    /// `add_stream_weight`.
+    pub(crate) fn checkpoint_reward(&mut self, staking_info: &mut StakingInfo, now: Timestamp) {
+        staking_info.pending_reward = staking_info
+            .pending_reward
+            .saturating_add(self.session_reward_until(staking_info, now));
+        staking_info.last_staked_at = now;
+        if staking_info.sets_version != self.sets_version {
+            self.update_completed_sets(staking_info);
+        }
+        self.update_streams(now);
+        staking_info.pending_stream_reward += self.stream_reward_of(staking_info, now);
+        staking_info.reward_per_weight_paid = self.reward_per_weight;
+        self.stream_weight -= self.staking_weight(staking_info);
+    }
+
+    pub(crate) fn add_stream_weight(&mut self, staking_info: &StakingInfo) {
+        self.stream_weight += self.staking_weight(staking_info);
+    }
+
+    /// Reward per second of the streams running at `now`.
+    fn stream_emission_per_second(&self, now: Timestamp) -> Balance {
+        self.streams
+            .iter()
+            .filter(|stream| stream.start_at <= now && now < stream.end_at)
+            .map(|stream| {
+                mul_div(stream.amount, NANOS_PER_SECOND, (stream.end_at - stream.start_at) as u128)
+            })
+            .sum()
+    }
+
+    fn loyalty_reached_at(staked_at: Timestamp, tier: &LoyaltyTier) -> Timestamp {
+        let duration = std::cmp::min(tier.days as u128 * NANOS_PER_DAY, Timestamp::MAX as u128);
+        staked_at.saturating_add(duration as Timestamp)
+    }
+
+    /// Bonus of the loyalty curve reached by a NFT staked at `staked_at`.
+    pub fn loyalty_bonus_bps(&self, staked_at: Timestamp, now: Timestamp) -> u32 {
+        self.terms
+            .loyalty_curve
+            .iter()
+            .rev()
+            .find(|tier| Self::loyalty_reached_at(staked_at, tier) <= now)
+            .map_or(0, |tier| tier.bonus_bps)
+    }
+
+    /// Bonus reward of the NFTs of `staking_info` over `[from, to)`, with
+    /// the curve effective at each time.
+    pub(crate) fn loyalty_reward_between(
+        &self,
+        staking_info: &StakingInfo,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Balance {
+        if self.kind != FarmKind::NonFungible {
+            return 0;
+        }
+        let mut reward: Balance = 0;
+        let mut segment_start = from;
+        for (replaced_at, loyalty_curve) in self.terms.loyalty_history.iter() {
+            if *replaced_at <= segment_start {
+                continue;
+            }
+            let segment_end = std::cmp::min(*replaced_at, to);
+            reward = reward.saturating_add(self.curve_reward_between(
+                loyalty_curve,
+                staking_info,
+                segment_start,
+                segment_end,
+            ));
+            segment_start = segment_end;
+            if segment_start >= to {
+                return reward;
+            }
+        }
+        reward.saturating_add(self.curve_reward_between(
+            &self.terms.loyalty_curve,
+            staking_info,
+            segment_start,
+            to,
+        ))
+    }
+
+    /// Bonus reward of `loyalty_curve` over `[from, to)`, each tier counted
+    /// from the time the NFT reached it.
+    fn curve_reward_between(
+        &self,
+        loyalty_curve: &[LoyaltyTier],
+        staking_info: &StakingInfo,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Balance {
+        if loyalty_curve.is_empty() || to <= from {
+            return 0;
+        }
+        let mut reward: Balance = 0;
+        for token_id in staking_info.token_balances.keys() {
+            let staked_at = match self.staked_nfts.get(token_id) {
+                Some(token) => token.staked_at,
+                None => continue,
+            };
+            let mut prev_bonus_bps = 0;
+            for tier in loyalty_curve.iter() {
+                let reached_at = Self::loyalty_reached_at(staked_at, tier);
+                if reached_at >= to {
+                    break;
+                }
+                reward = reward.saturating_add(mul_div(
+                    self.terms.reward_between(1, std::cmp::max(from, reached_at), to),
+                    (tier.bonus_bps - prev_bonus_bps) as u128,
+                    BP_DENOMINATOR,
+                ));
+                prev_bonus_bps = tier.bonus_bps;
+            }
+        }
+        reward
+    }
+
+    /// Reward of `staking_info` at the session rate over `[from, to)`,
+    /// with its set and loyalty bonuses.
+    pub(crate) fn session_reward_between(
+        &self,
+        staking_info: &StakingInfo,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Balance {
+        let from = std::cmp::max(from, self.terms.start_at);
+        if self.status != Status::Running || to <= from {
+            return 0;
+        }
+        let reward = self.terms.reward_between(self.staking_weight(staking_info), from, to);
+        reward
+            .saturating_add(mul_div(reward, staking_info.set_bonus_bps as u128, BP_DENOMINATOR))
+            .saturating_add(self.loyalty_reward_between(staking_info, from, to))
+    }
+
+    /// Session reward of `staking_info` since its `last_staked_at`, the
+    /// bonus of sets changed meanwhile counts from `sets_updated_at`.
+    pub(crate) fn session_reward_until(&self, staking_info: &StakingInfo, now: Timestamp) -> Balance {
+        let from = staking_info.last_staked_at;
+        if staking_info.sets_version == self.sets_version {
+            return self.session_reward_between(staking_info, from, now);
+        }
+        let changed_at = std::cmp::min(std::cmp::max(self.sets_updated_at, from), now);
+        let mut updated = staking_info.clone();
+        self.update_completed_sets(&mut updated);
+        self.session_reward_between(staking_info, from, changed_at)
+            + self.session_reward_between(&updated, changed_at, now)
+    }
+
+    /// Only looks at the NFTs of `staking_info`, `size` is the number of
+    /// accepted NFTs of an `AllWithPrefix` set.
+    fn is_set_completed(&self, nft_set: &NftSet, size: u64, staking_info: &StakingInfo) -> bool {
+        let staked = &staking_info.token_balances;
+        match &nft_set.rule {
+            SetRule::AllWithPrefix(prefix) => {
+                let staked_in_set = staked
+                    .keys()
+                    .filter(|token_id| {
+                        token_id.starts_with(prefix.as_str()) && self.accepted_nfts.contains(*token_id)
+                    })
+                    .count() as u64;
+                size > 0 && staked_in_set == size
+            }
+            SetRule::OneOfEach(prefixes) => prefixes.iter().all(|prefix| {
+                staked.keys().any(|token_id| token_id.starts_with(prefix.as_str()))
             }),
-            accepted_nfts
         }
     }
 
-    pub fn set_ended(&mut self, amount: Balance) {
-        self.amount_of_reward = 0;
-        self.amount_of_claimed += amount;
-        self.status = Status::Ended;
+    /// Recomputes the sets completed by the NFTs of `staking_info`, to be
+    /// called after `checkpoint_reward` when they change.
+    pub(crate) fn update_completed_sets(&self, staking_info: &mut StakingInfo) {
+        let completed: Vec<&NftSet> = self
+            .terms
+            .nft_sets
+            .iter()
+            .enumerate()
+            .filter(|(index, nft_set)| {
+                let size = self.set_sizes.get(*index).copied().unwrap_or(0);
+                self.is_set_completed(nft_set, size, staking_info)
+            })
+            .map(|(_, nft_set)| nft_set)
+            .collect();
+        staking_info.set_bonus_bps = completed.iter().map(|nft_set| nft_set.bonus_bps).sum();
+        staking_info.completed_sets = completed.into_iter().map(|nft_set| nft_set.name.clone()).collect();
+        staking_info.sets_version = self.sets_version;
+    }
+
+    /// Counts the accepted NFTs of each set again, when the sets change.
+    pub(crate) fn count_set_sizes(&mut self) {
+        let accepted_nfts = &self.accepted_nfts;
+        self.set_sizes = self
+            .terms
+            .nft_sets
+            .iter()
+            .map(|nft_set| match &nft_set.rule {
+                SetRule::AllWithPrefix(prefix) => accepted_nfts
+                    .iter()
+                    .filter(|token_id| token_id.starts_with(prefix.as_str()))
+                    .count() as u64,
+                SetRule::OneOfEach(_) => 0,
+            })
+            .collect();
+    }
+
+    /// Adds or removes an accepted NFT, keeping `set_sizes` in step.
+    pub(crate) fn set_nft_accepted(&mut self, token_id: &NFTTokenId, accepted: bool) {
+        let changed = if accepted {
+            self.accepted_nfts.insert(token_id.clone())
+        } else {
+            self.accepted_nfts.remove(token_id)
+        };
+        if !changed {
+            return;
+        }
+        for (nft_set, size) in self.terms.nft_sets.iter().zip(self.set_sizes.iter_mut()) {
+            if let SetRule::AllWithPrefix(prefix) = &nft_set.rule {
+                if token_id.starts_with(prefix.as_str()) {
+                    *size = if accepted { *size + 1 } else { *size - 1 };
+                }
+            }
+        }
+    }
+
+    /// Farmers recompute their completed sets from `now`.
+    pub(crate) fn touch_sets(&mut self, now: Timestamp) {
+        self.sets_version += 1;
+        self.sets_updated_at = now;
+    }
+
+    /// Takes what the farmer can be paid out of the session reward and the
+    /// stream reward, the farm ends when both are paid out.
+    pub(crate) fn take_reward(&mut self, staking_info: &mut StakingInfo, amount: Balance) -> Balance {
+        let stream_amount = std::cmp::min(
+            std::cmp::min(amount, staking_info.pending_stream_reward),
+            self.stream_reward,
+        );
+        let session_amount = std::cmp::min(amount - stream_amount, self.amount_of_reward);
+        staking_info.pending_stream_reward -= stream_amount;
+        staking_info.pending_reward = staking_info.pending_reward.saturating_sub(session_amount);
+        self.stream_reward -= stream_amount;
+        self.amount_of_reward -= session_amount;
+        self.amount_of_claimed += stream_amount + session_amount;
+        if self.amount_of_reward == 0 && self.stream_reward == 0 {
+            self.status = Status::Ended;
+        }
+        stream_amount + session_amount
+    }
+
+    pub fn can_be_funded_by(&self, account_id: &AccountId) -> bool {
+        account_id == &self.owner_id
+            || match &self.funding_policy {
+                FundingPolicy::OwnerOnly => false,
+                FundingPolicy::AllowList(funders) => funders.contains(account_id),
+                FundingPolicy::Anyone => true,
+            }
+    }
+
+    /// NFTs which can still be staked before `max_total_staked` is reached.
+    pub fn remaining_capacity(&self) -> Option<u64> {
+        self.terms
+            .max_total_staked
+            .map(|max_total_staked| max_total_staked.saturating_sub(self.staked_nfts.len()))
+    }
+
+    /// Nothing is staked, the reward left can not be earned anymore.
+    pub fn is_empty(&self) -> bool {
+        self.staked_nfts.is_empty() && self.total_staked == 0
+    }
+
+    /// Stops the reward at `now`, reward accrued before can be claimed
+    /// until `closing_at`.
+    pub(crate) fn start_closing(&mut self, now: Timestamp) {
+        self.update_streams(now);
+        self.streams.clear();
+        self.terms.rate_history.retain(|(effective_at, _)| *effective_at < now);
+        self.terms.rate_history.push((now, 0));
+        self.closing_at = Some(now + CLOSE_NOTICE);
     }
 
     pub(crate) fn add_reward(&mut self, amount: &Balance){
+        self.activate();
+        self.amount_of_reward += amount;
+    }
+
+    pub(crate) fn add_stream(&mut self, stream: Stream) {
+        self.activate();
+        self.update_streams(env::block_timestamp());
+        self.stream_reward += stream.amount;
+        self.streams.push(stream);
+    }
+
+    fn activate(&mut self) {
         match self.status {
             Status::Created => {
                 // When a farm gots first deposit of reward, it turns to Running state,
@@ -111,13 +706,10 @@ impl Farm {
                     // would trigger the farming
                     self.terms.start_at = env::block_timestamp();
                 }
-                self.amount_of_reward += amount;
-            },
-            Status::Running => {
-                self.amount_of_reward += amount;
             },
+            Status::Running => {},
             _ => {
-                env::panic(b"Farm is ended");
+                FarmingError::FarmEnded.panic();
             },
         }
     }
@@ -127,35 +719,142 @@ impl Farm {
 #[serde(crate = "near_sdk::serde")]
 pub struct FarmInfo {
     pub owner_id: String,
+    pub farm_kind: String,
     pub farm_status: String,
     pub seed_id: SeedId,
     pub start_at: u64,
     pub reward_per_session: U128,
     pub session_interval: u64,
+    pub rate_history: Vec<(u64, U128)>,
+    pub vesting: Option<VestingTerms>,
+    pub loyalty_curve: Vec<LoyaltyTier>,
+    pub nft_sets: Vec<NftSet>,
+    pub max_total_staked: Option<u64>,
+    pub max_per_wallet: Option<u64>,
+    pub remaining_capacity: Option<u64>,
     pub nft_contract_id: String,
+    pub ft_contract_id: Option<String>,
+    pub mt_contract_id: Option<String>,
 
     pub total_reward: U128,
+    /// Stream reward not paid yet, not part of `total_reward`.
+    pub stream_reward: U128,
     pub claimed_reward: U128,
     pub accepted_nfts: HashSet<NFTTokenId>,
     pub staked_ids: Vec<NFTTokenId>,
-    pub staked_nfts: Vec<StakedInfo>
+    pub staked_nfts: Vec<StakedInfo>,
+    pub total_staked: U128,
+    pub funding_policy: FundingPolicy,
+    pub total_contributed: U128,
+    pub refundable: U128,
+    /// Streams not fully released as `(start_at, end_at, amount)`.
+    pub streams: Vec<(u64, u64, U128)>,
+    pub closing_at: Option<u64>,
 }
 
 impl From<&Farm> for FarmInfo {
     fn from(farm: &Farm) -> Self {
         Self {
             owner_id: farm.owner_id.clone(),
+            farm_kind: (&farm.kind).into(),
             farm_status: (&farm.status).into(),
             seed_id: farm.terms.seed_id.clone(),
             start_at: farm.terms.start_at.into(),
-            reward_per_session: farm.terms.reward_per_session.into(),
+            reward_per_session: farm.terms.rate_at(env::block_timestamp()).into(),
             session_interval: farm.terms.session_interval.into(),
+            rate_history: farm
+                .terms
+                .rate_history
+                .iter()
+                .map(|(effective_at, rate)| (*effective_at, (*rate).into()))
+                .collect(),
+            vesting: farm.terms.vesting.clone(),
+            loyalty_curve: farm.terms.loyalty_curve.clone(),
+            nft_sets: farm.terms.nft_sets.clone(),
+            max_total_staked: farm.terms.max_total_staked,
+            max_per_wallet: farm.terms.max_per_wallet,
+            remaining_capacity: farm.remaining_capacity(),
             nft_contract_id: farm.nft_contract_id.clone(),
+            ft_contract_id: farm.ft_contract_id().cloned(),
+            mt_contract_id: farm.mt_contract_id().cloned(),
             total_reward: farm.amount_of_reward.into(),
+            stream_reward: farm.stream_reward.into(),
             claimed_reward: farm.amount_of_claimed.into(),
             accepted_nfts: farm.accepted_nfts.clone(),
             staked_ids: farm.staked_nfts.keys_as_vector().to_vec(),
-            staked_nfts: farm.staked_nfts.values_as_vector().to_vec()
+            staked_nfts: farm.staked_nfts.values_as_vector().to_vec(),
+            total_staked: farm.total_staked.into(),
+            funding_policy: farm.funding_policy.clone(),
+            total_contributed: farm.total_contributed.into(),
+            refundable: farm.refundable.into(),
+            streams: farm
+                .streams
+                .iter()
+                .map(|stream| (stream.start_at, stream.end_at, stream.amount.into()))
+                .collect(),
+            closing_at: farm.closing_at,
+        }
+    }
+}
+
+pub const NANOS_PER_SECOND: u128 = 1_000_000_000;
+pub const NANOS_PER_DAY: u128 = 86_400 * NANOS_PER_SECOND;
+
+/// Projections of a farm at the current rate and staking, the emission
+/// includes the running streams. Reward accrued but not claimed yet is still counted in the pool.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FarmStats {
+    pub emission_per_second: U128,
+    pub staked_nfts: u64,
+    pub total_weight: U128,
+    pub depleted_at: Option<U64>,
+    pub reward_per_nft_per_day: U128,
+    pub distributed_bps: u32,
+}
+
+impl Farm {
+    pub fn stats(&self, now: Timestamp) -> FarmStats {
+        let rate = self.terms.rate_at(now);
+        let session_interval = std::cmp::max(self.terms.session_interval, 1) as u128;
+        let total_weight = self.total_weight();
+        let emission_per_session = rate.saturating_mul(total_weight);
+
+        let depleted_at = if self.status == Status::Ended || emission_per_session == 0 {
+            None
+        } else {
+            let start_at = std::cmp::max(now, self.terms.start_at) as u128;
+            // whole sessions first, so that the duration can not overflow
+            let sessions = self.amount_of_reward / emission_per_session;
+            let rest = mul_div(
+                self.amount_of_reward % emission_per_session,
+                session_interval,
+                emission_per_session,
+            );
+            let depleted_at = start_at
+                .saturating_add(sessions.saturating_mul(session_interval))
+                .saturating_add(rest);
+            Some(U64(std::cmp::min(depleted_at, u64::MAX as u128) as u64))
+        };
+        let total_deposited = self
+            .amount_of_reward
+            .saturating_add(self.stream_reward)
+            .saturating_add(self.amount_of_claimed);
+        let distributed_bps = if total_deposited == 0 {
+            0
+        } else {
+            mul_div(self.amount_of_claimed, BP_DENOMINATOR, total_deposited) as u32
+        };
+
+        FarmStats {
+            emission_per_second: mul_div(emission_per_session, NANOS_PER_SECOND, session_interval)
+                .saturating_add(self.stream_emission_per_second(now))
+                .into(),
+            staked_nfts: self.staked_nfts.len(),
+            total_weight: total_weight.into(),
+            depleted_at,
+            reward_per_nft_per_day: mul_div(rate, NANOS_PER_DAY, session_interval).into(),
+            distributed_bps,
         }
     }
 }
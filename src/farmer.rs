@@ -1,20 +1,126 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::{AccountId, Balance, Timestamp};
+use near_sdk::json_types::U128;
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::collections::UnorderedMap;
-use crate::StorageKeys;
-use crate::FarmId;
+use std::collections::HashMap;
+use crate::{SeedId, StorageKeys};
+use crate::farm::{FarmId, FarmIdJson};
+use crate::utils::mul_div;
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, Serialize, Deserialize, PartialEq, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct StakingInfo {
     pub last_staked_at: Timestamp,
     pub amount: Balance,
+    /// Bonus of the NFT staked into a hybrid farm, in basis points.
+    pub boost_bps: u32,
+    /// Staked balance of each token id in a NFT or multi token farm.
+    pub token_balances: HashMap<String, Balance>,
+    /// Reward settled while NFTs staked by approval were not verified yet.
+    pub pending_reward: Balance,
+    /// Stream reward settled and not paid yet, paid from `Farm::stream_reward`.
+    pub pending_stream_reward: Balance,
+    /// `Farm::reward_per_weight` when the stream reward was last settled.
+    pub reward_per_weight_paid: u128,
+    /// Names of the `NftSet`s completed by the staked NFTs.
+    pub completed_sets: Vec<String>,
+    /// Sum of the bonuses of `completed_sets`, in basis points.
+    pub set_bonus_bps: u32,
+    /// `Farm::sets_version` when `completed_sets` were computed.
+    pub sets_version: u64,
+}
+
+impl StakingInfo {
+    pub fn new(last_staked_at: Timestamp) -> Self {
+        Self {
+            last_staked_at,
+            amount: 0,
+            boost_bps: 0,
+            token_balances: HashMap::new(),
+            pending_reward: 0,
+            pending_stream_reward: 0,
+            reward_per_weight_paid: 0,
+            completed_sets: vec![],
+            set_bonus_bps: 0,
+            sets_version: 0,
+        }
+    }
+}
+
+/// Claimed reward of one token vesting linearly from `start_at` to `end_at`.
+/// Reward vested later restarts the schedule with what is still locked,
+/// the part released until then is kept in `unlocked`.
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+pub struct VestingSchedule {
+    pub unlocked: Balance,
+    pub locked: Balance,
+    pub start_at: Timestamp,
+    pub end_at: Timestamp,
+}
+
+impl VestingSchedule {
+    pub fn new(now: Timestamp) -> Self {
+        Self {
+            unlocked: 0,
+            locked: 0,
+            start_at: now,
+            end_at: now,
+        }
+    }
+
+    /// Part of `locked` released until `timestamp`.
+    fn released_at(&self, timestamp: Timestamp) -> Balance {
+        if timestamp >= self.end_at {
+            self.locked
+        } else if timestamp <= self.start_at {
+            0
+        } else {
+            mul_div(
+                self.locked,
+                (timestamp - self.start_at) as u128,
+                (self.end_at - self.start_at) as u128,
+            )
+        }
+    }
+
+    pub fn unlocked_at(&self, timestamp: Timestamp) -> Balance {
+        self.unlocked + self.released_at(timestamp)
+    }
+
+    pub fn locked_at(&self, timestamp: Timestamp) -> Balance {
+        self.locked - self.released_at(timestamp)
+    }
+
+    /// Restarts the schedule at `now`, the locked reward vests until
+    /// `end_at` or later when the current schedule ends later.
+    pub fn add(&mut self, amount: Balance, now: Timestamp, end_at: Timestamp) {
+        let released = self.released_at(now);
+        self.unlocked += released;
+        self.locked = self.locked - released + amount;
+        self.start_at = now;
+        self.end_at = std::cmp::max(self.end_at, end_at);
+    }
+
+    /// Takes out the reward unlocked until `now`.
+    pub fn claim(&mut self, now: Timestamp) -> Balance {
+        let released = self.released_at(now);
+        let amount = self.unlocked + released;
+        self.unlocked = 0;
+        self.locked -= released;
+        self.start_at = std::cmp::min(now, self.end_at);
+        amount
+    }
 }
 
 #[derive(BorshSerialize, BorshDeserialize)]
 pub struct Farmer {
-    pub staking: UnorderedMap<FarmId, StakingInfo>
+    pub staking: UnorderedMap<FarmId, StakingInfo>,
+    /// Storage deposit above the minimum, pays registrations with reward tokens.
+    pub storage_balance: Balance,
+    pub vesting: UnorderedMap<SeedId, VestingSchedule>,
+    /// Settled reward whose transfer failed, paid by `claim_unpaid_reward`.
+    pub unpaid_rewards: HashMap<SeedId, Balance>,
 }
 
 impl Farmer {
@@ -23,6 +129,11 @@ impl Farmer {
             staking: UnorderedMap::new(StorageKeys::FarmerStaking {
                 account_id: farmer_id.clone()
             }),
+            storage_balance: 0,
+            vesting: UnorderedMap::new(StorageKeys::FarmerVesting {
+                account_id: farmer_id.clone()
+            }),
+            unpaid_rewards: HashMap::new(),
         }
     }
 }
@@ -30,15 +141,40 @@ impl Farmer {
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct FarmerInfo {
-    pub farm_staking: Vec<FarmId>,
-    pub staking_info: Vec<StakingInfo>
+    pub farm_staking: Vec<FarmIdJson>,
+    pub staking_info: Vec<StakingInfo>,
+    pub farm_kinds: Vec<String>
 }
 
-impl From<Farmer> for FarmerInfo {
-    fn from(farmer: Farmer) -> Self {
-        Self {
-            farm_staking: farmer.staking.keys_as_vector().to_vec(),
-            staking_info: farmer.staking.values_as_vector().to_vec()
-        }
-    }
+/// Staked token of a farmer in one farm, NFTs are locked until `unlock_at`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenPosition {
+    pub token_id: String,
+    pub balance: U128,
+    pub claimable: U128,
+    pub unlock_at: Timestamp,
+    pub locked: bool,
+    /// Reward multiplier of the loyalty curve, 10000 without bonus.
+    pub multiplier_bps: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FarmPosition {
+    pub farm_id: FarmIdJson,
+    pub farm_kind: String,
+    pub farm_status: String,
+    pub staked: U128,
+    pub claimable: U128,
+    pub tokens: Vec<TokenPosition>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VestingBalance {
+    pub seed_id: SeedId,
+    pub locked: U128,
+    pub unlocked: U128,
+    pub end_at: Timestamp,
 }
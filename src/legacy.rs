@@ -0,0 +1,79 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet};
+use near_sdk::{AccountId, Balance, Timestamp};
+use std::collections::HashSet;
+use crate::farm::{NFTTokenId, Status, Terms};
+use crate::SeedId;
+
+/// State of the first release, read once by `Contract::migrate`.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct ContractV0 {
+    pub owner_id: AccountId,
+    pub seeds: UnorderedMap<SeedId, SeedV0>,
+    pub farmers: LookupMap<AccountId, FarmerV0>,
+    pub farms: UnorderedMap<String, FarmV0>,
+    pub nft_contracts: UnorderedSet<AccountId>,
+    pub farmer_count: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct SeedV0 {
+    pub seed_id: SeedId,
+    pub next_index: u32,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+pub struct StakingInfoV0 {
+    pub last_staked_at: Timestamp,
+    pub amount: Balance,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct FarmerV0 {
+    /// Keyed by the `seed_id#index` farm id.
+    pub staking: UnorderedMap<String, StakingInfoV0>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+pub struct StakedInfoV0 {
+    pub owner_id: AccountId,
+    pub staked_at: Timestamp,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+pub struct TermsV0 {
+    pub seed_id: SeedId,
+    pub start_at: Timestamp,
+    pub reward_per_session: Balance,
+    pub session_interval: Timestamp,
+}
+
+impl From<TermsV0> for Terms {
+    fn from(terms: TermsV0) -> Self {
+        Terms {
+            seed_id: terms.seed_id,
+            start_at: terms.start_at,
+            reward_per_session: terms.reward_per_session,
+            session_interval: terms.session_interval,
+            rate_history: vec![],
+            vesting: None,
+            loyalty_curve: vec![],
+            loyalty_history: vec![],
+            nft_sets: vec![],
+            max_total_staked: None,
+            max_per_wallet: None,
+        }
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct FarmV0 {
+    pub owner_id: AccountId,
+    pub terms: TermsV0,
+    pub status: Status,
+    pub amount_of_reward: Balance,
+    pub amount_of_claimed: Balance,
+    pub nft_contract_id: AccountId,
+    pub staked_nfts: UnorderedMap<NFTTokenId, StakedInfoV0>,
+    pub accepted_nfts: HashSet<NFTTokenId>,
+}
@@ -1,21 +1,39 @@
 
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet};
-use near_sdk::{near_bindgen, PanicOnDefault, AccountId, BorshStorageKey, Promise, Gas, env};
+use near_sdk::{near_bindgen, PanicOnDefault, AccountId, Balance, BorshStorageKey, Promise, Gas, Timestamp, env};
 use near_sdk::json_types::ValidAccountId;
+use near_sdk::serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use crate::seed::*;
-pub use crate::farmer::FarmerInfo;
-pub use crate::farm::FarmInfo;
+pub use crate::nft_contract::{CreatorMode, NftContractConfig};
+pub use crate::role::Role;
+use crate::role::RoleSet;
+pub use crate::farmer::{FarmPosition, FarmerInfo, TokenPosition, VestingBalance};
+pub use crate::farm::{FarmInfo, FarmStats, FundingPolicy, LoyaltyTier, NftSet, SetRule, VestingTerms};
+pub use crate::actions_of_reward::ClaimAllResult;
+pub use crate::errors::FarmingError;
+pub use crate::seed::SeedInfo;
 use crate::farmer::*;
 use crate::farm::*;
-use crate::utils::{ext_ft};
+use crate::legacy::{ContractV0, FarmV0, FarmerV0};
+use crate::utils::{ext_ft, FT_STORAGE_DEPOSIT};
+use crate::errors::require;
 near_sdk::setup_alloc!();
 
 mod actions_of_farm;
+mod actions_of_nft_contract;
+mod actions_of_role;
 mod actions_of_reward;
+mod actions_of_seed;
+mod errors;
 mod seed;
 mod farmer;
 mod farm;
+mod legacy;
+mod nft_contract;
+mod role;
+mod events;
 mod utils;
 mod token_receiver;
 mod storage_impl;
@@ -29,18 +47,52 @@ pub struct Contract {
     seeds: UnorderedMap<SeedId, Seed>,
     farmers: LookupMap<AccountId, Farmer>,
     farms: UnorderedMap<FarmId, Farm>,
-    nft_contracts: UnorderedSet<AccountId>,
-    farmer_count: u64
+    nft_contracts: UnorderedMap<AccountId, NftContractConfig>,
+    farmer_count: u64,
+    approved_nfts: LookupMap<ContractNFTTokenId, FarmId>,
+    next_farm_id: FarmId,
+    legacy_farm_ids: LookupMap<String, FarmId>,
+    reward_tokens: UnorderedSet<SeedId>,
+    roles: LookupMap<AccountId, RoleSet>,
+    state: RunningState,
+    /// Farmers of the first release not migrated yet, see `migrate_farmers`.
+    legacy_farmers: LookupMap<AccountId, FarmerV0>,
+    /// Farms of the first release by `seed_id#index`, see `migrate_farms`.
+    legacy_farms: UnorderedMap<String, FarmV0>,
+    legacy_farms_left: u64,
 }
 
+/// Circuit breaker, see `assert_running`.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum RunningState {
+    Running,
+    Paused,
+}
+
+/// The first six keys are the prefixes of the first release,
+/// `migrate` moves the state under the keys that follow them.
 #[derive(BorshStorageKey, BorshSerialize)]
+#[allow(dead_code)]
 pub enum StorageKeys {
+    LegacyFarms,
+    LegacySeeds,
+    LegacyFarmers,
+    LegacyNFTContracts,
+    LegacyFarmerStaking { account_id: AccountId },
+    LegacyStakedNFTs { farm_id: String },
     Farms,
     Seeds,
     Farmers,
     NFTContracts,
     FarmerStaking { account_id: AccountId },
-    StakedNFTs { farm_id: FarmId }
+    FarmerVesting { account_id: AccountId },
+    StakedNFTs { farm_id: FarmId },
+    FarmContributions { farm_id: FarmId },
+    ApprovedNFTs,
+    LegacyFarmIds,
+    RewardTokens,
+    Roles
 }
 
 #[near_bindgen]
@@ -53,50 +105,307 @@ impl Contract {
             seeds: UnorderedMap::new(StorageKeys::Seeds),
             farmers: LookupMap::new(StorageKeys::Farmers),
             farms: UnorderedMap::new(StorageKeys::Farms),
-            nft_contracts: UnorderedSet::new(StorageKeys::NFTContracts)
+            nft_contracts: UnorderedMap::new(StorageKeys::NFTContracts),
+            approved_nfts: LookupMap::new(StorageKeys::ApprovedNFTs),
+            next_farm_id: 0,
+            legacy_farm_ids: LookupMap::new(StorageKeys::LegacyFarmIds),
+            reward_tokens: UnorderedSet::new(StorageKeys::RewardTokens),
+            roles: LookupMap::new(StorageKeys::Roles),
+            state: RunningState::Running,
+            legacy_farmers: LookupMap::new(StorageKeys::LegacyFarmers),
+            legacy_farms: UnorderedMap::new(StorageKeys::LegacyFarms),
+            legacy_farms_left: 0,
         }
     }
 
-    #[payable]
-    pub fn whitelist_nft_contract(&mut self, nft_contract_id: ValidAccountId) {
-        assert_eq!(
-            env::predecessor_account_id(),
-            self.owner_id,
-            "ERR_NOT_ALLOWED"
+    /// Moves the contract state of the first release under the new storage
+    /// keys, farms are moved by `migrate_farms` and farmers without staked
+    /// NFTs by `migrate_farmers`. The contract is paused until then.
+    #[init(ignore_state)]
+    #[private]
+    pub fn migrate() -> Self {
+        let mut old: ContractV0 = env::state_read().expect("ERR_NO_STATE");
+        let mut contract = Self {
+            owner_id: old.owner_id.clone(),
+            farmer_count: old.farmer_count,
+            seeds: UnorderedMap::new(StorageKeys::Seeds),
+            farmers: LookupMap::new(StorageKeys::Farmers),
+            farms: UnorderedMap::new(StorageKeys::Farms),
+            nft_contracts: UnorderedMap::new(StorageKeys::NFTContracts),
+            approved_nfts: LookupMap::new(StorageKeys::ApprovedNFTs),
+            next_farm_id: 0,
+            legacy_farm_ids: LookupMap::new(StorageKeys::LegacyFarmIds),
+            reward_tokens: UnorderedSet::new(StorageKeys::RewardTokens),
+            roles: LookupMap::new(StorageKeys::Roles),
+            state: RunningState::Paused,
+            legacy_farmers: old.farmers,
+            legacy_farms_left: old.farms.len(),
+            legacy_farms: old.farms,
+        };
+
+        for nft_contract_id in old.nft_contracts.iter() {
+            contract.nft_contracts.insert(
+                &nft_contract_id,
+                &NftContractConfig::new(nft_contract_id.clone()),
+            );
+        }
+        old.nft_contracts.clear();
+        for old_seed in old.seeds.values_as_vector().iter() {
+            let mut seed = Seed::new(old_seed.seed_id.clone());
+            seed.next_index = old_seed.next_index;
+            contract.reward_tokens.insert(&seed.seed_id);
+            contract.seeds.insert(&seed.seed_id, &seed);
+        }
+        old.seeds.clear();
+        contract
+    }
+
+    /// Moves up to `limit` farms of the first release from `from_index`,
+    /// with the NFTs staked in them. Farms get a global id and keep their
+    /// `seed_id#index` id as an alias, farms moved already are skipped.
+    pub fn migrate_farms(&mut self, from_index: u64, limit: u64) {
+        self.assert_role(Role::Owner);
+        require(self.state == RunningState::Paused, FarmingError::NotAllowed);
+        let keys = self.legacy_farms.keys_as_vector();
+        let legacy_farm_ids: Vec<String> = (from_index..std::cmp::min(from_index + limit, keys.len()))
+            .map(|index| keys.get(index).unwrap())
+            .collect();
+        for legacy_farm_id in legacy_farm_ids {
+            if self.legacy_farm_ids.contains_key(&legacy_farm_id) {
+                continue;
+            }
+            let mut old_farm = self.legacy_farms.get(&legacy_farm_id).unwrap();
+            self.internal_migrate_farm(legacy_farm_id.clone(), &mut old_farm);
+            self.legacy_farms.insert(&legacy_farm_id, &old_farm);
+            self.legacy_farms_left -= 1;
+        }
+        env::log(format!("{} farms of the first release left to migrate", self.legacy_farms_left).as_bytes());
+    }
+
+    /// Moves farmers of the first release under the new storage keys once
+    /// all farms are moved, anyone can call it.
+    pub fn migrate_farmers(&mut self, account_ids: Vec<ValidAccountId>) {
+        require(self.legacy_farms_left == 0, FarmingError::MigrationPending);
+        for account_id in account_ids {
+            self.internal_migrate_farmer(&account_id.into());
+        }
+    }
+
+    /// The contract runs again once the first release is migrated.
+    pub fn set_running_state(&mut self, state: RunningState) {
+        self.assert_role(Role::Guardian);
+        require(
+            state == RunningState::Paused || self.legacy_farms_left == 0,
+            FarmingError::MigrationPending,
         );
-        self.nft_contracts.insert(&nft_contract_id.into());
+        env::log(format!("Contract state changed to {:?}", state).as_bytes());
+        self.state = state;
+    }
+
+    pub fn get_running_state(&self) -> RunningState {
+        self.state
     }
 
     #[payable]
     pub fn ft_deposit(&mut self, ft_account: ValidAccountId) -> Promise {
-        assert!(env::attached_deposit() >= 1250000000000000000000, "You need attach more yocto");
+        require(
+            env::attached_deposit() >= FT_STORAGE_DEPOSIT,
+            FarmingError::NotEnoughDeposit,
+        );
         ext_ft::storage_deposit(
             env::current_account_id(),
             true,
             &ft_account,
-            1250000000000000000000,
+            FT_STORAGE_DEPOSIT,
             GAS_FOR_FT_DEPOSIT,
         )
     }
 
-    pub fn is_whitelist_nft_contract(&self, nft_contract_id: &AccountId) -> bool {
-        self.nft_contracts.contains(nft_contract_id)
-    }
-
     pub fn list_seeds(&self) -> Vec<String> {
         self.seeds.keys_as_vector().to_vec()
     }
 
-    pub fn get_farm(&self, farm_id: FarmId) -> FarmInfo {
-        let farm = self.farms.get(&farm_id).unwrap();
-        FarmInfo::from(&farm)
+    pub fn get_farm(&self, farm_id: FarmIdJson) -> Option<FarmInfo> {
+        let farm = self.internal_get_farm(&farm_id)?;
+        Some(FarmInfo::from(&farm))
     }
 
-    pub fn get_farmer(&self, account_id: AccountId) -> FarmerInfo {
-        FarmerInfo::from(self.farmers.get(&account_id).unwrap())
+    /// Staking of both NFT and FT farms, `farm_kinds` follows `farm_staking`.
+    pub fn get_farmer(&self, account_id: AccountId) -> Option<FarmerInfo> {
+        let farmer = self.farmers.get(&account_id)?;
+        let farm_ids = farmer.staking.keys_as_vector().to_vec();
+        Some(FarmerInfo {
+            farm_staking: farm_ids.iter().map(|farm_id| farm_id.to_string()).collect(),
+            staking_info: farmer.staking.values_as_vector().to_vec(),
+            farm_kinds: farm_ids
+                .iter()
+                .map(|farm_id| {
+                    self.farms
+                        .get(farm_id)
+                        .map(|farm| (&farm.kind).into())
+                        .unwrap_or_default()
+                })
+                .collect(),
+        })
     }
 
-    pub fn get_seed(&self, seed_id: SeedId) -> Seed {
-        self.seeds.get(&seed_id).unwrap()
+    /// Positions of the farmer in each farm, `None` for an unregistered account.
+    pub fn get_farmer_portfolio(
+        &self,
+        account_id: AccountId,
+        from_index: u64,
+        limit: u64,
+    ) -> Option<Vec<FarmPosition>> {
+        let farmer = self.farmers.get(&account_id)?;
+        let keys = farmer.staking.keys_as_vector();
+        let now = env::block_timestamp();
+
+        Some(
+            (from_index..std::cmp::min(from_index + limit, keys.len()))
+                .filter_map(|index| {
+                    let farm_id = keys.get(index).unwrap();
+                    let farm = self.farms.get(&farm_id)?;
+                    let staking_info = farmer.staking.get(&farm_id)?;
+                    let claimable = self.internal_get_claimable(&farm_id, &account_id, now);
+                    let tokens = staking_info
+                        .token_balances
+                        .iter()
+                        .map(|(token_id, balance)| {
+                            let staked_at = farm.staked_nfts.get(token_id).map(|token| token.staked_at);
+                            let unlock_at =
+                                staked_at.map_or(0, |staked_at| staked_at + farm.terms.session_interval);
+                            let bonus_bps =
+                                staked_at.map_or(0, |staked_at| farm.loyalty_bonus_bps(staked_at, now));
+                            TokenPosition {
+                                token_id: token_id.clone(),
+                                balance: (*balance).into(),
+                                claimable: (claimable * balance / std::cmp::max(staking_info.amount, 1)).into(),
+                                unlock_at,
+                                locked: unlock_at > now,
+                                multiplier_bps: BP_DENOMINATOR as u32 + bonus_bps,
+                            }
+                        })
+                        .collect();
+                    Some(FarmPosition {
+                        farm_id: farm_id.to_string(),
+                        farm_kind: (&farm.kind).into(),
+                        farm_status: (&farm.status).into(),
+                        staked: staking_info.amount.into(),
+                        claimable: claimable.into(),
+                        tokens,
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    pub fn get_seed(&self, seed_id: SeedId) -> Option<SeedInfo> {
+        self.seeds.get(&seed_id).map(|seed| SeedInfo::from(&seed))
+    }
+}
+
+impl Contract {
+    /// Farms creation, funding, staking and reward payments stop while
+    /// paused, withdrawing and unstaking keep working.
+    pub(crate) fn assert_running(&self) {
+        require(self.state == RunningState::Running, FarmingError::ContractPaused);
+    }
+
+    /// Moves a farm of the first release and the staking of its farmers,
+    /// the staked NFTs are cleared from `old_farm`.
+    fn internal_migrate_farm(&mut self, legacy_farm_id: String, old_farm: &mut FarmV0) {
+        let farm_id = self.next_farm_id;
+        self.next_farm_id += 1;
+        let mut farm = Farm::new(
+            old_farm.owner_id.clone(),
+            farm_id,
+            FarmKind::NonFungible,
+            old_farm.terms.clone().into(),
+            old_farm.nft_contract_id.clone(),
+            old_farm.accepted_nfts.clone(),
+        );
+        farm.status = old_farm.status.clone();
+        farm.amount_of_reward = old_farm.amount_of_reward;
+        farm.amount_of_claimed = old_farm.amount_of_claimed;
+        let deposited = old_farm.amount_of_reward + old_farm.amount_of_claimed;
+        if deposited > 0 {
+            farm.contributions.insert(&old_farm.owner_id, &deposited);
+            farm.total_contributed = deposited;
+        }
+
+        // token ids staked by each farmer, with the earliest stake
+        let mut staked_tokens: HashMap<AccountId, (Timestamp, Vec<NFTTokenId>)> = HashMap::new();
+        for (token_id, staked_info) in old_farm.staked_nfts.iter() {
+            farm.staked_nfts.insert(
+                &token_id,
+                &StakedInfo {
+                    owner_id: staked_info.owner_id.clone(),
+                    staked_at: staked_info.staked_at,
+                    approval_id: None,
+                },
+            );
+            let (staked_at, token_ids) = staked_tokens
+                .entry(staked_info.owner_id)
+                .or_insert((staked_info.staked_at, vec![]));
+            *staked_at = std::cmp::min(*staked_at, staked_info.staked_at);
+            token_ids.push(token_id);
+        }
+        farm.stream_weight = farm.staked_nfts.len() as Balance;
+        old_farm.staked_nfts.clear();
+
+        for (account_id, (staked_at, token_ids)) in staked_tokens {
+            let last_staked_at = self
+                .legacy_farmers
+                .get(&account_id)
+                .and_then(|old_farmer| old_farmer.staking.get(&legacy_farm_id))
+                .map_or(staked_at, |old_staking_info| old_staking_info.last_staked_at);
+            let mut staking_info = StakingInfo::new(last_staked_at);
+            staking_info.amount = token_ids.len() as Balance;
+            for token_id in token_ids {
+                staking_info.token_balances.insert(token_id, 1);
+            }
+            let mut farmer = self
+                .farmers
+                .get(&account_id)
+                .unwrap_or_else(|| Farmer::new(account_id.clone()));
+            farmer.staking.insert(&farm_id, &staking_info);
+            self.farmers.insert(&account_id, &farmer);
+        }
+
+        if let Some(mut config) = self.nft_contracts.get(&old_farm.nft_contract_id) {
+            config.farm_count += 1;
+            self.nft_contracts.insert(&old_farm.nft_contract_id, &config);
+        }
+        let seed_id = old_farm.terms.seed_id.clone();
+        let mut seed = self.seeds.get(&seed_id).unwrap_or_else(|| Seed::new(seed_id.clone()));
+        seed.farm_ids.push(farm_id);
+        seed.total_deposited += deposited;
+        seed.total_claimed += old_farm.amount_of_claimed;
+        if farm.status == Status::Running {
+            seed.active_farms += 1;
+        }
+        self.seeds.insert(&seed_id, &seed);
+        self.reward_tokens.insert(&seed_id);
+        self.legacy_farm_ids.insert(&legacy_farm_id, &farm_id);
+        self.farms.insert(&farm_id, &farm);
     }
-}
\ No newline at end of file
+
+    /// Moves a farmer of the first release under the new storage keys, the
+    /// staking in its farms is moved by `migrate_farms` before.
+    pub(crate) fn internal_migrate_farmer(&mut self, account_id: &AccountId) {
+        if self.legacy_farms_left != 0 {
+            return;
+        }
+        let mut old_farmer = match self.legacy_farmers.get(account_id) {
+            Some(old_farmer) => old_farmer,
+            None => return,
+        };
+        let farmer = self
+            .farmers
+            .get(account_id)
+            .unwrap_or_else(|| Farmer::new(account_id.clone()));
+        old_farmer.staking.clear();
+        self.legacy_farmers.remove(account_id);
+        self.farmers.insert(account_id, &farmer);
+    }
+}
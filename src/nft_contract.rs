@@ -0,0 +1,55 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::AccountId;
+use std::collections::HashSet;
+
+/// Who may create farms for a whitelisted NFT contract.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub enum CreatorMode {
+    /// Creator of all accepted NFTs, checked by `is_creator_of_nfts` of the NFT contract.
+    #[default]
+    CreatorOfNfts,
+    /// Admins of the NFT contract set by the owner.
+    Admins,
+    /// Owner of the NFT contract, checked by its `owner_id` view.
+    ContractOwner,
+    /// Anyone.
+    Permissionless,
+}
+
+/// Registry entry of a whitelisted NFT contract.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftContractConfig {
+    pub name: String,
+    pub creator_mode: CreatorMode,
+    /// Admins allowed to create farms in `CreatorMode::Admins`.
+    pub farm_creators: HashSet<AccountId>,
+    pub max_farms: Option<u64>,
+    /// Bonus in basis points given in hybrid farms by staking a NFT of this
+    /// contract, other farm kinds ignore it.
+    pub boost_bps: u32,
+    pub enabled: bool,
+    /// Number of NFT farms created for this contract.
+    #[serde(default)]
+    pub farm_count: u64,
+}
+
+impl NftContractConfig {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            creator_mode: CreatorMode::default(),
+            farm_creators: HashSet::new(),
+            max_farms: None,
+            boost_bps: 0,
+            enabled: true,
+            farm_count: 0,
+        }
+    }
+
+    pub fn can_add_farm(&self) -> bool {
+        self.max_farms.map_or(true, |max_farms| self.farm_count < max_farms)
+    }
+}
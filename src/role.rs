@@ -0,0 +1,49 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+
+/// Administrative roles. `Owner` includes all the others,
+/// `owner_id` of the contract is always `Owner`.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Role {
+    Owner,
+    /// Manages the NFT contract registry.
+    Operator,
+    /// Pauses the contract.
+    Guardian,
+    /// Manages who creates farms of whitelisted NFT contracts.
+    FarmManager,
+}
+
+pub const ALL_ROLES: [Role; 4] = [Role::Owner, Role::Operator, Role::Guardian, Role::FarmManager];
+
+impl Role {
+    fn mask(&self) -> u8 {
+        1 << (*self as u8)
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Default, Clone, Copy)]
+pub struct RoleSet(u8);
+
+impl RoleSet {
+    pub fn contains(&self, role: Role) -> bool {
+        self.0 & role.mask() != 0
+    }
+
+    pub fn insert(&mut self, role: Role) {
+        self.0 |= role.mask();
+    }
+
+    pub fn remove(&mut self, role: Role) {
+        self.0 &= !role.mask();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn roles(&self) -> Vec<Role> {
+        ALL_ROLES.iter().filter(|role| self.contains(**role)).cloned().collect()
+    }
+}
@@ -1,21 +1,64 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
 use near_sdk::serde::{Deserialize, Serialize};
-use near_sdk::{AccountId};
+use near_sdk::{AccountId, Balance};
+use crate::farm::{FarmId, FarmIdJson};
 
 pub(crate) type SeedId = AccountId;
 
-#[derive(BorshSerialize, BorshDeserialize, Deserialize, Serialize)]
-#[serde(crate = "near_sdk::serde")]
+#[derive(BorshSerialize, BorshDeserialize)]
 pub struct Seed {
     pub seed_id: SeedId,
     pub next_index: u32,
+    /// From `ft_metadata` of the seed, `None` until the call succeeded.
+    pub symbol: Option<String>,
+    pub decimals: Option<u8>,
+    pub farm_ids: Vec<FarmId>,
+    pub total_deposited: Balance,
+    pub total_claimed: Balance,
+    /// Farms in `Running` status.
+    pub active_farms: u64,
 }
 
 impl Seed {
     pub fn new(seed_id: SeedId) -> Self {
         Self {
             seed_id: seed_id,
-            next_index: 0
+            next_index: 0,
+            symbol: None,
+            decimals: None,
+            farm_ids: vec![],
+            total_deposited: 0,
+            total_claimed: 0,
+            active_farms: 0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SeedInfo {
+    pub seed_id: SeedId,
+    pub next_index: u32,
+    pub symbol: Option<String>,
+    pub decimals: Option<u8>,
+    pub farm_ids: Vec<FarmIdJson>,
+    pub total_deposited: U128,
+    pub total_claimed: U128,
+    pub active_farms: u64,
+}
+
+impl From<&Seed> for SeedInfo {
+    fn from(seed: &Seed) -> Self {
+        Self {
+            seed_id: seed.seed_id.clone(),
+            next_index: seed.next_index,
+            symbol: seed.symbol.clone(),
+            decimals: seed.decimals,
+            farm_ids: seed.farm_ids.iter().map(|farm_id| farm_id.to_string()).collect(),
+            total_deposited: seed.total_deposited.into(),
+            total_claimed: seed.total_claimed.into(),
+            active_farms: seed.active_farms,
         }
     }
 }
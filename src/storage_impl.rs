@@ -6,6 +6,7 @@ use std::convert::TryInto;
 
 use near_sdk::json_types::{ValidAccountId, U128};
 use near_sdk::{assert_one_yocto, env, near_bindgen, Promise};
+use crate::errors::{require, FarmingError};
 use crate::*;
 
 pub const STORAGE_BALANCE_MIN_BOUND: u128 = 100_000_000_000_000_000_000_000;
@@ -13,7 +14,8 @@ pub const STORAGE_BALANCE_MIN_BOUND: u128 = 100_000_000_000_000_000_000_000;
 /// Implements users storage management for the pool.
 #[near_bindgen]
 impl StorageManagement for Contract {
-    #[allow(unused_variables)]
+    /// Deposit above the minimum is kept as storage balance
+    /// unless `registration_only` is set.
     #[payable]
     fn storage_deposit(
         &mut self,
@@ -24,48 +26,80 @@ impl StorageManagement for Contract {
         let account_id = account_id
             .map(|a| a.into())
             .unwrap_or_else(|| env::predecessor_account_id());
-        let already_registered = self.farmers.contains_key(&account_id);
+        // accounts registered before the migration are registered already
+        self.internal_migrate_farmer(&account_id);
+        let already_registered =
+            self.farmers.contains_key(&account_id) || self.legacy_farmers.contains_key(&account_id);
         if amount < STORAGE_BALANCE_MIN_BOUND && !already_registered {
-            env::panic("Not deposit enough storage".as_bytes());
+            FarmingError::NotEnoughStorageDeposit.panic();
         }
 
-        if already_registered {
-            if amount > 0 {
-                Promise::new(env::predecessor_account_id()).transfer(amount);
-            }
+        let registration_only = registration_only.unwrap_or(false);
+        let (mut farmer, extra) = if already_registered {
+            let farmer = self
+                .farmers
+                .get(&account_id)
+                .unwrap_or_else(|| Farmer::new(account_id.clone()));
+            (farmer, amount)
         } else {
-            self.farmers.insert(&account_id, &Farmer::new(account_id.clone()));
             self.farmer_count += 1;
-            let refund = amount - STORAGE_BALANCE_MIN_BOUND;
-            if refund > 0 {
-                Promise::new(env::predecessor_account_id()).transfer(refund);
+            (Farmer::new(account_id.clone()), amount - STORAGE_BALANCE_MIN_BOUND)
+        };
+        if registration_only {
+            if extra > 0 {
+                Promise::new(env::predecessor_account_id()).transfer(extra);
             }
+        } else {
+            farmer.storage_balance += extra;
         }
+        self.farmers.insert(&account_id, &farmer);
         self.storage_balance_of(account_id.try_into().unwrap()).unwrap()
     }
 
-    #[allow(unused_variables)]
     #[payable]
     fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
         assert_one_yocto();
 
         let account_id = env::predecessor_account_id();
-        Promise::new(account_id.clone()).transfer(amount.unwrap().into());
+        let mut farmer = self.internal_unwrap_farmer(&account_id);
+        let amount = amount.map_or(farmer.storage_balance, |amount| amount.0);
+        require(
+            amount <= farmer.storage_balance,
+            FarmingError::NotEnoughStorageBalance,
+        );
+        farmer.storage_balance -= amount;
+        self.farmers.insert(&account_id, &farmer);
+        if amount > 0 {
+            Promise::new(account_id.clone()).transfer(amount);
+        }
         self.storage_balance_of(account_id.try_into().unwrap()).unwrap()
     }
 
+    /// Refused while the farmer has stake, vesting or unpaid reward.
     #[allow(unused_variables)]
     #[payable]
     fn storage_unregister(&mut self, force: Option<bool>) -> bool {
         assert_one_yocto();
         // force option is useless, leave it for compatible consideration.
-        // User should withdraw all his rewards and seeds token before unregister!
         let account_id = env::predecessor_account_id();
-        if let Some(farmer) = self.farmers.get(&account_id) {
-            // todo: how about his rps lookup map? maybe already cleaned when unstake all seeds
+        if let Some(mut farmer) = self.farmers.get(&account_id) {
+            require(
+                farmer.staking.values().all(|staking_info| {
+                    staking_info.amount == 0
+                        && staking_info.boost_bps == 0
+                        && staking_info.pending_reward == 0
+                        && staking_info.pending_stream_reward == 0
+                })
+                    && farmer.vesting.is_empty()
+                    && farmer.unpaid_rewards.values().all(|amount| *amount == 0)
+                    && !self.legacy_farmers.contains_key(&account_id),
+                FarmingError::FarmerNotEmpty,
+            );
+            farmer.staking.clear();
             self.farmers.remove(&account_id);
             self.farmer_count -= 1;
-            Promise::new(account_id.clone()).transfer(STORAGE_BALANCE_MIN_BOUND);
+            Promise::new(account_id.clone())
+                .transfer(STORAGE_BALANCE_MIN_BOUND + farmer.storage_balance);
             true
         } else {
             false
@@ -80,13 +114,11 @@ impl StorageManagement for Contract {
     }
 
     fn storage_balance_of(&self, account_id: ValidAccountId) -> Option<StorageBalance> {
-        if self.farmers.contains_key(&account_id.into()) {
-            Some(StorageBalance {
-                total: U128(STORAGE_BALANCE_MIN_BOUND),
-                available: U128(0),
+        self.farmers
+            .get(&account_id.into())
+            .map(|farmer| StorageBalance {
+                total: U128(STORAGE_BALANCE_MIN_BOUND + farmer.storage_balance),
+                available: U128(farmer.storage_balance),
             })
-        } else {
-            None
-        }
     }
 }
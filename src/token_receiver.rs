@@ -1,27 +1,69 @@
+use crate::errors::{require, FarmingError};
+use crate::events::Event;
 use crate::*;
 use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_contract_standards::non_fungible_token::approval::NonFungibleTokenApprovalReceiver;
 use near_contract_standards::non_fungible_token::core::NonFungibleTokenReceiver;
-use near_sdk::json_types::U128;
+use near_sdk::json_types::{U128, U64};
 use near_sdk::serde::{Deserialize, Serialize};
-use near_sdk::{env, serde_json, PromiseOrValue};
+use near_sdk::{env, serde_json, Balance, PromiseOrValue};
+/// Action of tokens received via `ft_transfer_call`, e.g.
+/// `{"action": "fund_farm", "farm_id": "0"}`. The `action` tag picks the
+/// format, a msg without it is read as a `FTReceiverMessage`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum FTAction {
+    /// Adds the tokens as reward of the farm.
+    FundFarm { farm_id: FarmIdJson },
+    /// Stakes the tokens into a FT farm.
+    StakeFt { farm_id: FarmIdJson },
+    /// Adds the tokens as reward of the farm, released over `[start_at, end_at)`.
+    AddStream { farm_id: FarmIdJson, start_at: U64, end_at: U64 },
+}
+
 /// Message parameters to receive via token function call.
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
+#[serde(deny_unknown_fields)]
 pub struct FTReceiverMessage {
-    farm_id: FarmId
+    farm_id: FarmIdJson,
+    /// Stake the tokens into a FT farm instead of adding them as reward.
+    #[serde(default)]
+    stake: bool
 }
 
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct NFTReceiverMessage {
-    farm_id: FarmId
+    farm_id: FarmIdJson
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MTReceiverMessage {
+    farm_id: FarmIdJson
+}
+
+/// Receiver of NEP-245 `mt_transfer_call` and `mt_batch_transfer_call`.
+pub trait MultiTokenReceiver {
+    fn mt_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_ids: Vec<AccountId>,
+        token_ids: Vec<NFTTokenId>,
+        amounts: Vec<U128>,
+        msg: String,
+    ) -> PromiseOrValue<Vec<U128>>;
 }
 
 #[near_bindgen]
 impl FungibleTokenReceiver for Contract {
     /// Callback on receiving tokens by this contract.
-    /// transfer reward token with specific msg indicate
-    /// which farm to be deposited to.
+    /// transfer reward token with a `FTAction` msg indicate
+    /// which farm to be funded or staked into. Tokens of a msg
+    /// which can not be handled, or received while the contract
+    /// is paused, are refunded with an event.
     fn ft_on_transfer(
         &mut self,
         sender_id: ValidAccountId,
@@ -29,24 +71,28 @@ impl FungibleTokenReceiver for Contract {
         msg: String,
     ) -> PromiseOrValue<U128> {
         let sender: AccountId = sender_id.into();
-        let amount: u128 = amount.into();
-        let seed_id = env::predecessor_account_id();
-        if msg.is_empty() {
-            env::panic(b"We do not support FT staking.");
-        } else {
-            let message = serde_json::from_str::<FTReceiverMessage>(&msg).expect("Wrong format");
-            if message.farm_id.is_empty() {
-                return PromiseOrValue::Value(U128(amount));
-            } else {
-                self.internal_add_reward_to_farm(sender, seed_id, message.farm_id, amount);
+        let token_id = env::predecessor_account_id();
+        match self.internal_ft_on_transfer(&sender, &token_id, amount.into(), &msg) {
+            Ok(()) => PromiseOrValue::Value(U128(0)),
+            Err(error) => {
+                Event::FtTransferRefunded {
+                    sender_id: &sender,
+                    token_id: &token_id,
+                    amount,
+                    code: error.code(),
+                    message: error.message(),
+                }
+                .emit();
+                PromiseOrValue::Value(amount)
             }
-            PromiseOrValue::Value(U128(0))
         }
     }
 }
 
 #[near_bindgen]
 impl NonFungibleTokenReceiver for Contract {
+    /// Stakes the NFT into the farm in msg, a full farm or wallet
+    /// gives it back with an event.
     fn nft_on_transfer(
         &mut self,
         _sender_id: AccountId,
@@ -54,26 +100,37 @@ impl NonFungibleTokenReceiver for Contract {
         token_id: NFTTokenId,
         msg: String,
     ) -> PromiseOrValue<bool> {
+        self.assert_running();
         let nft_contract_id = env::predecessor_account_id();
         let signer_id = env::signer_account_id();
 
-        assert_ne!(
-            nft_contract_id, signer_id,
-            "nft_on_approve should only be called via cross-contract call"
-        );
+        require(nft_contract_id != signer_id, FarmingError::NotCrossContractCall);
 
-        assert_eq!(
-            previous_owner_id,
-            signer_id,
-            "Owner_id should be signer_id"
-        );
+        require(previous_owner_id == signer_id, FarmingError::OwnerNotSigner);
 
         if msg.is_empty() {
-            env::panic(b"Not found farm_id args");
+            FarmingError::MissingFarmId.panic();
         } else {
-            let message = serde_json::from_str::<NFTReceiverMessage>(&msg).expect("Wrong format");
+            let message = serde_json::from_str::<NFTReceiverMessage>(&msg).unwrap_or_else(|_| FarmingError::WrongMessage.panic());
             if !message.farm_id.is_empty() {
-                self.internal_stake(message.farm_id, previous_owner_id, nft_contract_id, token_id);
+                let farm_id = self.internal_unwrap_farm_id(&message.farm_id);
+                if let Err(error) = self.internal_stake(
+                    farm_id,
+                    previous_owner_id.clone(),
+                    nft_contract_id.clone(),
+                    token_id.clone(),
+                    None,
+                ) {
+                    Event::NftTransferRefunded {
+                        owner_id: &previous_owner_id,
+                        nft_contract_id: &nft_contract_id,
+                        token_id: &token_id,
+                        code: error.code(),
+                        message: error.message(),
+                    }
+                    .emit();
+                    return PromiseOrValue::Value(true);
+                }
                 return PromiseOrValue::Value(false);
             } else {
                 return PromiseOrValue::Value(true);
@@ -81,3 +138,124 @@ impl NonFungibleTokenReceiver for Contract {
         }
     }
 }
+
+#[near_bindgen]
+impl NonFungibleTokenApprovalReceiver for Contract {
+    /// Stakes the NFT without taking it, the owner approves this contract
+    /// with the farm in msg and keeps the NFT.
+    fn nft_on_approve(
+        &mut self,
+        token_id: NFTTokenId,
+        owner_id: AccountId,
+        approval_id: u64,
+        msg: String,
+    ) -> PromiseOrValue<String> {
+        self.assert_running();
+        let nft_contract_id = env::predecessor_account_id();
+        let signer_id = env::signer_account_id();
+
+        require(nft_contract_id != signer_id, FarmingError::NotCrossContractCall);
+
+        require(owner_id == signer_id, FarmingError::OwnerNotSigner);
+
+        if msg.is_empty() {
+            FarmingError::MissingFarmId.panic();
+        } else {
+            let message = serde_json::from_str::<NFTReceiverMessage>(&msg).unwrap_or_else(|_| FarmingError::WrongMessage.panic());
+            if message.farm_id.is_empty() {
+                FarmingError::MissingFarmId.panic();
+            }
+            let farm_id = self.internal_unwrap_farm_id(&message.farm_id);
+            self.internal_stake(farm_id, owner_id, nft_contract_id, token_id, Some(approval_id))
+                .unwrap_or_else(|error| error.panic());
+            PromiseOrValue::Value(farm_id.to_string())
+        }
+    }
+}
+
+#[near_bindgen]
+impl MultiTokenReceiver for Contract {
+    /// Stakes the received tokens into the multi token farm in msg.
+    fn mt_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_ids: Vec<AccountId>,
+        token_ids: Vec<NFTTokenId>,
+        amounts: Vec<U128>,
+        msg: String,
+    ) -> PromiseOrValue<Vec<U128>> {
+        self.assert_running();
+        let mt_contract_id = env::predecessor_account_id();
+
+        require(
+            previous_owner_ids.iter().all(|owner_id| owner_id == &sender_id),
+            FarmingError::OwnerNotSigner,
+        );
+
+        if msg.is_empty() {
+            FarmingError::MissingFarmId.panic();
+        } else {
+            let message = serde_json::from_str::<MTReceiverMessage>(&msg).unwrap_or_else(|_| FarmingError::WrongMessage.panic());
+            if !message.farm_id.is_empty() {
+                let farm_id = self.internal_unwrap_farm_id(&message.farm_id);
+                self.internal_stake_mt(farm_id, sender_id, mt_contract_id, &token_ids, &amounts);
+                return PromiseOrValue::Value(vec![U128(0); token_ids.len()]);
+            } else {
+                return PromiseOrValue::Value(amounts);
+            }
+        }
+    }
+}
+
+impl Contract {
+    fn internal_ft_on_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        token_id: &AccountId,
+        amount: Balance,
+        msg: &str,
+    ) -> Result<(), FarmingError> {
+        if self.state != RunningState::Running {
+            return Err(FarmingError::ContractPaused);
+        }
+        if msg.is_empty() {
+            return Err(FarmingError::MissingFarmId);
+        }
+        let message = serde_json::from_str::<serde_json::Value>(msg).map_err(|_| FarmingError::WrongMessage)?;
+        let action = if message.get("action").is_some() {
+            serde_json::from_value::<FTAction>(message).map_err(|_| FarmingError::WrongMessage)?
+        } else {
+            match serde_json::from_value::<FTReceiverMessage>(message).map_err(|_| FarmingError::WrongMessage)? {
+                FTReceiverMessage { farm_id, stake: true } => FTAction::StakeFt { farm_id },
+                FTReceiverMessage { farm_id, stake: false } => FTAction::FundFarm { farm_id },
+            }
+        };
+        match action {
+            FTAction::FundFarm { farm_id } => {
+                let farm_id = self
+                    .internal_parse_farm_id(&farm_id)
+                    .ok_or(FarmingError::FarmNotFound)?;
+                self.internal_add_reward_to_farm(sender_id.clone(), token_id.clone(), farm_id, amount)
+            }
+            FTAction::StakeFt { farm_id } => {
+                let farm_id = self
+                    .internal_parse_farm_id(&farm_id)
+                    .ok_or(FarmingError::FarmNotFound)?;
+                self.internal_stake_ft(farm_id, sender_id.clone(), token_id.clone(), amount)
+            }
+            FTAction::AddStream { farm_id, start_at, end_at } => {
+                let farm_id = self
+                    .internal_parse_farm_id(&farm_id)
+                    .ok_or(FarmingError::FarmNotFound)?;
+                self.internal_add_stream_to_farm(
+                    sender_id.clone(),
+                    token_id.clone(),
+                    farm_id,
+                    amount,
+                    start_at.into(),
+                    end_at.into(),
+                )
+            }
+        }
+    }
+}
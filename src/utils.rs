@@ -1,10 +1,33 @@
-use crate::farm::{NFTTokenId, TermsJson};
-use crate::FarmId;
-use near_sdk::{env, ext_contract, Gas};
-use std::collections::HashSet;
+use crate::farm::{ContractNFTTokenId, NFTTokenId, TermsJson};
+use crate::errors::FarmingError;
+use crate::{CreatorMode, FarmId, SeedId};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Deserialize;
+use near_sdk::{env, ext_contract, AccountId, Balance, Gas};
+use std::collections::{HashMap, HashSet};
 pub const GAS_FOR_NFT_TRANSFER: Gas = 10_000_000_000_000;
 pub const GAS_FOR_FT_TRANSFER: Gas = 10_000_000_000_000;
+pub const GAS_FOR_MT_TRANSFER: Gas = 15_000_000_000_000;
+pub const GAS_FOR_NFT_VIEW: Gas = 5_000_000_000_000;
+pub const GAS_FOR_FT_VIEW: Gas = 5_000_000_000_000;
 pub const XCC_GAS: Gas = 20000000000000;
+/// Gas of `storage_balance_of` and `callback_claim_user_reward`,
+/// which may register the farmer before the transfer.
+pub const GAS_FOR_CLAIM_REWARD: Gas = 55_000_000_000_000;
+pub const GAS_FOR_CLAIM_CALLBACK: Gas = 50_000_000_000_000;
+pub const GAS_FOR_RESOLVE_REWARD: Gas = 10_000_000_000_000;
+/// Gas of the callbacks putting back a stake whose transfer failed.
+pub const GAS_FOR_RESOLVE_UNSTAKE: Gas = 15_000_000_000_000;
+/// Gas of `callback_verify_approved_nfts`, which may pay the reward
+/// and give back a NFT.
+pub const GAS_FOR_VERIFY_CALLBACK: Gas = XCC_GAS + GAS_FOR_CLAIM_REWARD + GAS_FOR_NFT_TRANSFER;
+/// Approved NFTs whose `nft_token` views and callback fit in one call.
+pub const MAX_VERIFY_NFTS: usize = 40;
+/// Storage deposit of one account in a FT contract.
+pub const FT_STORAGE_DEPOSIT: Balance = 1_250_000_000_000_000_000_000;
+/// Gas kept to settle one more farm in `claim_all`.
+pub const GAS_FOR_CLAIM_FARM: Gas = 5_000_000_000_000;
+pub const NFT_DELIMETER: char = ':';
 #[ext_contract(ext_ft)]
 trait FungibleToken {
     // change methods
@@ -20,6 +43,7 @@ trait FungibleToken {
     // view methods
     fn ft_total_supply(&self) -> String;
     fn ft_balance_of(&self, account_id: String) -> String;
+    fn ft_metadata(&self) -> FungibleTokenMetadata;
 
     fn storage_deposit(account_id: String, registration_only: bool) -> StorageBalance;
     fn storage_balance_of(account_id: String) -> StorageBalance;
@@ -41,6 +65,47 @@ trait NonFungibleToken {
     fn storage_balance_of(account_id: String) -> StorageBalance;
 
     fn is_creator_of_nfts(account_id: String, tokens_set: HashSet<String>) -> bool;
+
+    // view methods
+    fn nft_token(&self, token_id: String) -> Option<NftToken>;
+
+    fn owner_id(&self) -> String;
+}
+
+/// Part of the NEP-171 token needed to verify NFTs staked by approval.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftToken {
+    pub owner_id: AccountId,
+    #[serde(default)]
+    pub approved_account_ids: Option<HashMap<AccountId, u64>>,
+}
+
+impl NftToken {
+    pub fn is_approved(&self, owner_id: &AccountId, account_id: &AccountId, approval_id: u64) -> bool {
+        &self.owner_id == owner_id
+            && self
+                .approved_account_ids
+                .as_ref()
+                .and_then(|approved_account_ids| approved_account_ids.get(account_id))
+                == Some(&approval_id)
+    }
+}
+
+#[ext_contract(ext_mt)]
+trait MultiToken {
+    // change methods
+    fn mt_batch_transfer(
+        &mut self,
+        receiver_id: String,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+        approvals: Option<Vec<Option<(String, u64)>>>,
+        memo: Option<String>,
+    );
+
+    // view methods
+    fn mt_batch_balance_of(&self, account_id: String, token_ids: Vec<String>) -> Vec<U128>;
 }
 
 #[ext_contract(ext_self)]
@@ -51,12 +116,127 @@ pub trait FarmActions {
         nft_contract_id: AccountId,
         accepted_nfts: HashSet<NFTTokenId>,
     );
+
+    fn callback_check_contract_owner(
+        owner_id: AccountId,
+        terms: TermsJson,
+        nft_contract_id: AccountId,
+        accepted_nfts: HashSet<NFTTokenId>,
+    );
+
+    fn callback_add_accepted_nfts(
+        farm_id: FarmId,
+        tokens: HashSet<NFTTokenId>,
+        creator_mode: CreatorMode,
+        deposit: U128,
+    );
+
+    fn callback_set_seed_metadata(seed_id: SeedId);
+
+    fn callback_claim_user_reward(
+        sender_id: AccountId,
+        seed_id: SeedId,
+        farm_id: Option<FarmId>,
+        amount: U128,
+    );
+
+    fn callback_resolve_reward(
+        sender_id: AccountId,
+        seed_id: SeedId,
+        farm_id: Option<FarmId>,
+        amount: U128,
+    );
+
+    fn callback_claim_refund(sender_id: AccountId, farm_id: FarmId, contribution: U128);
+
+    fn callback_resolve_unstake_ft(farm_id: FarmId, sender_id: AccountId, amount: U128);
+
+    fn callback_resolve_unstake_mt(
+        farm_id: FarmId,
+        sender_id: AccountId,
+        token_ids: Vec<NFTTokenId>,
+        amounts: Vec<U128>,
+    );
+
+    fn callback_verify_approved_nfts(
+        farm_id: FarmId,
+        farmer_id: AccountId,
+        token_ids: Vec<NFTTokenId>,
+        withdraw_token_id: Option<NFTTokenId>,
+    );
+
+    fn callback_evict_approved_nfts(farm_id: FarmId, token_ids: Vec<NFTTokenId>);
+}
+
+/// Farm id checked to be an index or a legacy `{seed_id}#{index}`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidFarmId {
+    Index(FarmId),
+    Legacy { seed_id: SeedId, index: u32 },
 }
 
-pub fn parse_farm_id(farm_id: &FarmId) -> (String, usize) {
-    let v: Vec<&str> = farm_id.split("#").collect();
-    if v.len() != 2 {
-        env::panic(b"Farm id not found")
+impl TryFrom<&str> for ValidFarmId {
+    type Error = FarmingError;
+
+    fn try_from(farm_id: &str) -> Result<Self, Self::Error> {
+        if let Ok(index) = farm_id.parse::<FarmId>() {
+            return Ok(Self::Index(index));
+        }
+        let (seed_id, index) = farm_id.split_once('#').ok_or(FarmingError::WrongFarmId)?;
+        if !env::is_valid_account_id(seed_id.as_bytes()) {
+            return Err(FarmingError::WrongFarmId);
+        }
+        let index = index.parse::<u32>().map_err(|_| FarmingError::WrongFarmId)?;
+        Ok(Self::Legacy {
+            seed_id: seed_id.to_string(),
+            index,
+        })
+    }
+}
+
+impl std::fmt::Display for ValidFarmId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "{}", index),
+            Self::Legacy { seed_id, index } => write!(f, "{}#{}", seed_id, index),
+        }
+    }
+}
+
+pub fn contract_token_id(nft_contract_id: &AccountId, token_id: &NFTTokenId) -> ContractNFTTokenId {
+    format!("{}{}{}", nft_contract_id, NFT_DELIMETER, token_id)
+}
+
+pub fn parse_contract_token_id(contract_token_id: &ContractNFTTokenId) -> (AccountId, NFTTokenId) {
+    match contract_token_id.split_once(NFT_DELIMETER) {
+        Some((nft_contract_id, token_id)) => (nft_contract_id.to_string(), token_id.to_string()),
+        None => FarmingError::WrongContractTokenId.panic(),
+    }
+}
+
+/// `a * b / d` rounded down, the product may overflow `u128`,
+/// a result above `u128::MAX` saturates.
+pub fn mul_div(a: u128, b: u128, d: u128) -> u128 {
+    const MASK: u128 = u64::MAX as u128;
+    let (a0, a1, b0, b1) = (a & MASK, a >> 64, b & MASK, b >> 64);
+    let (p00, p01, p10, p11) = (a0 * b0, a0 * b1, a1 * b0, a1 * b1);
+    let mid = (p00 >> 64) + (p01 & MASK) + (p10 & MASK);
+    let lo = (p00 & MASK) | (mid << 64);
+    let hi = p11 + (p01 >> 64) + (p10 >> 64) + (mid >> 64);
+
+    // long division of the 256 bits product
+    let (mut quotient, mut remainder) = (0u128, 0u128);
+    for bit in (0..256).rev() {
+        let next = if bit >= 128 { (hi >> (bit - 128)) & 1 } else { (lo >> bit) & 1 };
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | next;
+        if carry == 1 || remainder >= d {
+            remainder = remainder.wrapping_sub(d);
+            if bit >= 128 {
+                return u128::MAX;
+            }
+            quotient |= 1 << bit;
+        }
     }
-    (v[0].to_string(), v[1].parse::<usize>().unwrap())
+    quotient
 }
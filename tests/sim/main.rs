@@ -1,11 +1,11 @@
-use near_sdk::{serde_json::json, json_types::{U64, U128}};
-use near_sdk_sim::{init_simulator, UserAccount, DEFAULT_GAS, STORAGE_AMOUNT, to_yocto};
-use farming::{FarmInfo, FarmerInfo};
+use near_sdk::{serde_json::{self, json}, json_types::{U64, U128}};
+use near_sdk_sim::{init_simulator, ExecutionResult, UserAccount, DEFAULT_GAS, STORAGE_AMOUNT, to_yocto};
+use farming::{ClaimAllResult, CreatorMode, FarmInfo, FarmPosition, FarmStats, FarmerInfo, FarmingError, LoyaltyTier, NftContractConfig, NftSet, Role, RunningState, SeedInfo, SetRule, VestingBalance, VestingTerms};
 
 near_sdk_sim::lazy_static_include::lazy_static_include_bytes!{
     FT_CONTRACT_WASM_FILE => "tests/source/fungible_token.wasm",
     NFT_CONTRACT_WASM_FILE => "tests/source/non_fungible_token.wasm",
-    FARMING_CONTRACT_WASM_FILE => "tests/source/farming.wasm"
+    FARMING_CONTRACT_WASM_FILE => "target/wasm32-unknown-unknown/release/farming.wasm"
 }
 
 const FT_CONTRACT_ID: &str = "ft_contract";
@@ -145,6 +145,17 @@ pub fn init() -> (UserAccount, UserAccount, UserAccount, UserAccount, UserAccoun
         1
     ).assert_success();
 
+    // Allow the FT as reward token
+    root.call(
+        farming_contract.account_id(), 
+        "approve_reward_token", 
+        &json!({
+            "token_id": ft_contract.account_id()
+        }).to_string().as_bytes(), 
+        DEFAULT_GAS, 
+        to_yocto("0.00125")
+    ).assert_success();
+
     // Deposit FT contract for farming contract
     artist.call(
         farming_contract.account_id(), 
@@ -408,4 +419,2760 @@ pub fn test_withdraw_farm() {
     ).unwrap_json();
     assert_ne!(farm_info_v1.claimed_reward.0, 0);
     assert_eq!(farm_info_v1.staked_nfts.len(), 0);
-}
\ No newline at end of file
+}
+#[test]
+pub fn test_stake_ft_farm() {
+    let (root, artist, collector, ft_contract, _nft_contract, farming_contract) = init();
+
+    //create FT farm
+    artist.call(
+        farming_contract.account_id(), 
+        "create_ft_farm", 
+        &json!({
+            "terms": {
+                "seed_id": ft_contract.account_id(),
+                "start_at": "0",
+                "reward_per_session": U128(RPS),
+                "session_interval": "1"
+            },
+            "ft_contract_id": ft_contract.account_id(),
+            "stake_unit": U128(1000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    let mut farm_1 = ft_contract.account_id().clone();
+    farm_1.push_str("#1");
+
+    // a small reward, all of it is accrued after a session
+    artist.call(
+        ft_contract.account_id(), 
+        "ft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "amount": U128(1000),
+            "msg": &json!({"farm_id": farm_1.clone()}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+
+    artist.call(
+        ft_contract.account_id(), 
+        "ft_transfer", 
+        &json!({
+            "receiver_id": collector.account_id(),
+            "amount": U128(100000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+
+    //stake FT
+    collector.call(
+        ft_contract.account_id(), 
+        "ft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "amount": U128(100000),
+            "msg": &json!({"farm_id": farm_1.clone(), "stake": true}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+
+    let farmer_info_v1: FarmerInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farmer", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farmer_info_v1.farm_staking[1], "1");
+    assert_eq!(farmer_info_v1.staking_info[1].amount, 100000);
+    assert_eq!(farmer_info_v1.farm_kinds[1], "Fungible");
+
+    let farm_info_v1: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": farm_1.clone()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farm_info_v1.farm_kind, "Fungible");
+    assert_eq!(farm_info_v1.total_staked.0, 100000);
+
+    let claimable: Option<U128> = root.view(
+        farming_contract.account_id(), 
+        "get_claimable_amount", 
+        &json!({
+            "farm_id": farm_1.clone(),
+            "farmer_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(claimable, Some(U128(1000)));
+
+    //unstake FT
+    collector.call(
+        farming_contract.account_id(), 
+        "unstake_ft", 
+        &json!({
+            "farm_id": farm_1.clone(),
+            "amount": U128(40000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+
+    // the unstaked tokens and the reward are back
+    let balance: U128 = root.view(
+        ft_contract.account_id(), 
+        "ft_balance_of", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(balance.0, 41000);
+
+    let farmer_info_v2: FarmerInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farmer", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farmer_info_v2.staking_info[1].amount, 60000);
+
+    let farm_info_v2: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "1"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farm_info_v2.total_staked.0, 60000);
+    assert_eq!(farm_info_v2.claimed_reward.0, 1000);
+}
+
+#[test]
+pub fn test_large_ft_stake() {
+    let (root, artist, collector, ft_contract, _nft_contract, farming_contract) = init();
+    let one_token: u128 = 1_000_000_000_000_000_000_000_000;
+
+    // a whole token per session and unit, the accrual is far above u128
+    artist.call(
+        farming_contract.account_id(), 
+        "create_ft_farm", 
+        &json!({
+            "terms": {
+                "seed_id": ft_contract.account_id(),
+                "start_at": "0",
+                "reward_per_session": U128(one_token),
+                "session_interval": "1"
+            },
+            "ft_contract_id": ft_contract.account_id(),
+            "stake_unit": U128(1)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    artist.call(
+        ft_contract.account_id(), 
+        "ft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "amount": U128(1000 * one_token),
+            "msg": &json!({"farm_id": "1"}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    artist.call(
+        ft_contract.account_id(), 
+        "ft_transfer", 
+        &json!({
+            "receiver_id": collector.account_id(),
+            "amount": U128(100 * one_token)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    collector.call(
+        ft_contract.account_id(), 
+        "ft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "amount": U128(100 * one_token),
+            "msg": &json!({"farm_id": "1", "stake": true}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+
+    collector.call(
+        farming_contract.account_id(), 
+        "claim_reward_by_farm", 
+        &json!({
+            "farm_id": "1"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "1"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farm_info.farm_status, "Ended");
+    assert_eq!(farm_info.claimed_reward.0, 1000 * one_token);
+
+    collector.call(
+        farming_contract.account_id(), 
+        "unstake_ft", 
+        &json!({
+            "farm_id": "1",
+            "amount": U128(100 * one_token)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    let balance: U128 = root.view(
+        ft_contract.account_id(), 
+        "ft_balance_of", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(balance.0, 1100 * one_token);
+}
+
+#[test]
+pub fn test_ft_top_up_keeps_accrual() {
+    let (root, artist, collector, ft_contract, _nft_contract, farming_contract) = init();
+
+    //create FT farm with a session of one day
+    artist.call(
+        farming_contract.account_id(), 
+        "create_ft_farm", 
+        &json!({
+            "terms": {
+                "seed_id": ft_contract.account_id(),
+                "start_at": "0",
+                "reward_per_session": U128(100000000000000000000),
+                "session_interval": "86400000000000"
+            },
+            "ft_contract_id": ft_contract.account_id(),
+            "stake_unit": U128(1000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    artist.call(
+        ft_contract.account_id(), 
+        "ft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "amount": U128(1000000000000000000000000),
+            "msg": &json!({"farm_id": "1"}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    artist.call(
+        ft_contract.account_id(), 
+        "ft_transfer", 
+        &json!({
+            "receiver_id": collector.account_id(),
+            "amount": U128(200000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+
+    for _ in 0..2 {
+        collector.call(
+            ft_contract.account_id(), 
+            "ft_transfer_call", 
+            &json!({
+                "receiver_id": farming_contract.account_id(),
+                "amount": U128(100000),
+                "msg": &json!({"farm_id": "1", "stake": true}).to_string()
+            }).to_string().as_bytes(),
+            DEFAULT_GAS, 
+            1
+        ).assert_success();
+    }
+
+    // the top up inside the session keeps what the first stake accrued
+    let farmer_info: FarmerInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farmer", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farmer_info.staking_info[1].amount, 200000);
+    assert!(farmer_info.staking_info[1].pending_reward > 0);
+}
+
+#[test]
+pub fn test_emergency_withdraw() {
+    let (root, artist, collector, ft_contract, nft_contract, farming_contract) = init();
+
+    artist.call(
+        farming_contract.account_id(), 
+        "create_ft_farm", 
+        &json!({
+            "terms": {
+                "seed_id": ft_contract.account_id(),
+                "start_at": "0",
+                "reward_per_session": U128(RPS),
+                "session_interval": "1"
+            },
+            "ft_contract_id": ft_contract.account_id(),
+            "stake_unit": U128(1000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    artist.call(
+        ft_contract.account_id(), 
+        "ft_transfer", 
+        &json!({
+            "receiver_id": collector.account_id(),
+            "amount": U128(100000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    collector.call(
+        ft_contract.account_id(), 
+        "ft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "amount": U128(100000),
+            "msg": &json!({"farm_id": "1", "stake": true}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+
+    root.call(
+        farming_contract.account_id(), 
+        "set_running_state", 
+        &json!({
+            "state": "Paused"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "claim_reward_by_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert_failure(outcome, FarmingError::ContractPaused);
+    let outcome = artist.call(
+        farming_contract.account_id(), 
+        "close_farm", 
+        &json!({
+            "farm_id": "1"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::ContractPaused);
+
+    // stake can leave while paused
+    collector.call(
+        farming_contract.account_id(), 
+        "unstake_ft", 
+        &json!({
+            "farm_id": "1",
+            "amount": U128(50000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    let balance: U128 = root.view(
+        ft_contract.account_id(), 
+        "ft_balance_of", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(balance.0, 50000);
+
+    // the NFT and the FT come back while paused
+    collector.call(
+        farming_contract.account_id(), 
+        "emergency_withdraw", 
+        &json!({
+            "farm_id": "0",
+            "token_ids": [NFT_ID_1]
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    let token: serde_json::Value = root.view(
+        nft_contract.account_id(), 
+        "nft_token", 
+        &json!({
+            "token_id": NFT_ID_1
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(token["owner_id"], collector.account_id());
+
+    collector.call(
+        farming_contract.account_id(), 
+        "emergency_withdraw", 
+        &json!({
+            "farm_id": "1",
+            "token_ids": []
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    let balance: U128 = root.view(
+        ft_contract.account_id(), 
+        "ft_balance_of", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(balance.0, 100000);
+
+    // tokens sent while paused are refunded
+    let outcome = collector.call(
+        ft_contract.account_id(), 
+        "ft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "amount": U128(100000),
+            "msg": &json!({"farm_id": "1", "stake": true}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert!(outcome
+        .promise_results()
+        .iter()
+        .flatten()
+        .any(|result| result
+            .logs()
+            .iter()
+            .any(|log| log.contains(FarmingError::ContractPaused.code()))));
+    let balance: U128 = root.view(
+        ft_contract.account_id(), 
+        "ft_balance_of", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(balance.0, 100000);
+
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "1"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farm_info.total_staked.0, 0);
+}
+
+#[test]
+pub fn test_emergency_withdraw_skips_reward() {
+    let (root, artist, collector, ft_contract, _nft_contract, farming_contract) = init();
+
+    // the accrual of this farm is far more than it holds
+    artist.call(
+        farming_contract.account_id(), 
+        "create_ft_farm", 
+        &json!({
+            "terms": {
+                "seed_id": ft_contract.account_id(),
+                "start_at": "0",
+                "reward_per_session": U128(u128::MAX),
+                "session_interval": "1"
+            },
+            "ft_contract_id": ft_contract.account_id(),
+            "stake_unit": U128(1)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    artist.call(
+        ft_contract.account_id(), 
+        "ft_transfer", 
+        &json!({
+            "receiver_id": collector.account_id(),
+            "amount": U128(100000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    collector.call(
+        ft_contract.account_id(), 
+        "ft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "amount": U128(100000),
+            "msg": &json!({"farm_id": "1", "stake": true}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+
+    collector.call(
+        farming_contract.account_id(), 
+        "emergency_withdraw", 
+        &json!({
+            "farm_id": "1",
+            "token_ids": []
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    let balance: U128 = root.view(
+        ft_contract.account_id(), 
+        "ft_balance_of", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(balance.0, 100000);
+
+    let farmer_info: FarmerInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farmer", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farmer_info.staking_info[1].amount, 0);
+    assert_eq!(farmer_info.staking_info[1].pending_reward, 0);
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "1"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farm_info.total_staked.0, 0);
+}
+
+#[test]
+pub fn test_failed_unstake_ft_restores_stake() {
+    let (root, artist, collector, ft_contract, _nft_contract, farming_contract) = init();
+
+    artist.call(
+        farming_contract.account_id(), 
+        "create_ft_farm", 
+        &json!({
+            "terms": {
+                "seed_id": ft_contract.account_id(),
+                "start_at": "0",
+                "reward_per_session": U128(RPS),
+                "session_interval": "1"
+            },
+            "ft_contract_id": ft_contract.account_id(),
+            "stake_unit": U128(1000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    artist.call(
+        ft_contract.account_id(), 
+        "ft_transfer", 
+        &json!({
+            "receiver_id": collector.account_id(),
+            "amount": U128(100000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    collector.call(
+        ft_contract.account_id(), 
+        "ft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "amount": U128(100000),
+            "msg": &json!({"farm_id": "1", "stake": true}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+
+    // the transfer back fails once the collector left the FT contract
+    collector.call(
+        ft_contract.account_id(), 
+        "storage_unregister", 
+        &json!({}).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    collector.call(
+        farming_contract.account_id(), 
+        "unstake_ft", 
+        &json!({
+            "farm_id": "1",
+            "amount": U128(100000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    let farmer_info: FarmerInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farmer", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farmer_info.staking_info[1].amount, 100000);
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "1"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farm_info.total_staked.0, 100000);
+
+    collector.call(
+        ft_contract.account_id(), 
+        "storage_deposit", 
+        &json!({}).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        to_yocto("0.126")
+    ).assert_success();
+    collector.call(
+        farming_contract.account_id(), 
+        "unstake_ft", 
+        &json!({
+            "farm_id": "1",
+            "amount": U128(100000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    let balance: U128 = root.view(
+        ft_contract.account_id(), 
+        "ft_balance_of", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(balance.0, 100000);
+}
+
+#[test]
+pub fn test_accepted_nfts() {
+    let (root, artist, collector, ft_contract, nft_contract, farming_contract) = init();
+
+    for (minter, token_id) in [(&artist, "token-4"), (&collector, "token-5")] {
+        minter.call(
+            nft_contract.account_id(), 
+            "nft_mint", 
+            &json!({
+                "token_id": token_id,
+                "metadata": {
+                    "title": token_id
+                },
+                "receiver_id": minter.account_id()
+            }).to_string().as_bytes(),
+            DEFAULT_GAS, 
+            to_yocto("1")
+        ).assert_success();
+    }
+
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "add_accepted_nfts", 
+        &json!({
+            "farm_id": "0",
+            "tokens": ["token-5"]
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        to_yocto("0.1")
+    );
+    assert_failure(outcome, FarmingError::NotFarmOwner);
+
+    artist.call(
+        farming_contract.account_id(), 
+        "add_accepted_nfts", 
+        &json!({
+            "farm_id": "0",
+            "tokens": ["token-4"]
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        to_yocto("0.1")
+    ).assert_success();
+
+    // the artist did not create token-5, the deposit is refunded
+    let outcome = artist.call(
+        farming_contract.account_id(), 
+        "add_accepted_nfts", 
+        &json!({
+            "farm_id": "0",
+            "tokens": ["token-5"]
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        to_yocto("0.1")
+    );
+    assert!(outcome.promise_results().iter().flatten().any(|result| {
+        result.logs().iter().any(|log| {
+            log.contains("accepted_nfts_refunded") && log.contains(FarmingError::NotNftsOwner.code())
+        })
+    }));
+
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert!(farm_info.accepted_nfts.contains("token-4"));
+    assert!(!farm_info.accepted_nfts.contains("token-5"));
+
+    artist.call(
+        farming_contract.account_id(), 
+        "remove_accepted_nfts", 
+        &json!({
+            "farm_id": "0",
+            "tokens": ["token-4"]
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert!(!farm_info.accepted_nfts.contains("token-4"));
+
+    artist.call(
+        farming_contract.account_id(), 
+        "create_ft_farm", 
+        &json!({
+            "terms": {
+                "seed_id": ft_contract.account_id(),
+                "start_at": "0",
+                "reward_per_session": U128(RPS),
+                "session_interval": "1"
+            },
+            "ft_contract_id": ft_contract.account_id(),
+            "stake_unit": U128(1000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    let outcome = artist.call(
+        farming_contract.account_id(), 
+        "remove_accepted_nfts", 
+        &json!({
+            "farm_id": "1",
+            "tokens": ["token-1"]
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::WrongFarmKind);
+}
+
+#[test]
+pub fn test_view_unknown_ids() {
+    let (root, _artist, collector, _ft_contract, _nft_contract, farming_contract) = init();
+
+    let farm_info: Option<FarmInfo> = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "not-a-farm-id"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert!(farm_info.is_none());
+
+    let farmer_info: Option<FarmerInfo> = root.view(
+        farming_contract.account_id(), 
+        "get_farmer", 
+        &json!({
+            "account_id": "unknown"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert!(farmer_info.is_none());
+
+    for farm_id in ["not-a-farm-id", "ft_contract#x", "99"] {
+        let claimable: Option<U128> = root.view(
+            farming_contract.account_id(), 
+            "get_claimable_amount", 
+            &json!({
+                "farm_id": farm_id,
+                "farmer_id": collector.account_id()
+            }).to_string().as_bytes()
+        ).unwrap_json();
+        assert!(claimable.is_none());
+    }
+    let claimable: Option<U128> = root.view(
+        farming_contract.account_id(), 
+        "get_claimable_amount", 
+        &json!({
+            "farm_id": "ft_contract#0",
+            "farmer_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert!(claimable.is_some());
+}
+
+fn assert_failure(outcome: ExecutionResult, error: FarmingError) {
+    assert!(!outcome.is_ok());
+    assert!(format!("{:?}", outcome.status()).contains(error.code()));
+}
+
+#[test]
+pub fn test_error_codes() {
+    let (root, _artist, collector, _ft_contract, _nft_contract, farming_contract) = init();
+
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "claim_reward_by_farm", 
+        &json!({
+            "farm_id": "unknown#0"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert_failure(outcome, FarmingError::FarmNotFound);
+
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "claim_reward_by_farm", 
+        &json!({
+            "farm_id": "unknown#first"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert_failure(outcome, FarmingError::WrongFarmId);
+
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "ft_on_transfer", 
+        &json!({
+            "sender_id": collector.account_id(),
+            "amount": U128(100),
+            "msg": ""
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_eq!(outcome.unwrap_json::<U128>().0, 100);
+    assert!(outcome.logs()[0].contains(FarmingError::MissingFarmId.code()));
+
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "storage_deposit", 
+        &json!({}).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert_failure(outcome, FarmingError::NotEnoughStorageDeposit);
+
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "set_running_state", 
+        &json!({
+            "state": "Paused"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::NotAllowed);
+
+    let outcome = root.call(
+        farming_contract.account_id(), 
+        "set_creator_mode", 
+        &json!({
+            "nft_contract_id": collector.account_id(),
+            "creator_mode": "Permissionless"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::NftContractNotWhitelisted);
+}
+
+#[test]
+pub fn test_seed_info() {
+    let (root, _artist, _collector, ft_contract, _nft_contract, farming_contract) = init();
+
+    let seeds: Vec<SeedInfo> = root.view(
+        farming_contract.account_id(), 
+        "list_seeds_info", 
+        &json!({
+            "from_index": 0,
+            "limit": 10
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(seeds.len(), 1);
+    assert_eq!(seeds[0].seed_id, ft_contract.account_id());
+    assert_eq!(seeds[0].decimals, Some(24));
+    assert_eq!(seeds[0].farm_ids, vec!["0".to_string()]);
+    assert_eq!(seeds[0].total_deposited.0, 100000000000000);
+    assert_eq!(seeds[0].active_farms, 1);
+}
+
+#[test]
+pub fn test_reward_token_allow_list() {
+    let (root, artist, _collector, _ft_contract, nft_contract, farming_contract) = init();
+
+    let is_reward_token: bool = root.view(
+        farming_contract.account_id(), 
+        "is_reward_token", 
+        &json!({
+            "token_id": nft_contract.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert!(!is_reward_token);
+
+    let outcome = artist.call(
+        farming_contract.account_id(), 
+        "create_ft_farm", 
+        &json!({
+            "terms": {
+                "seed_id": nft_contract.account_id(),
+                "start_at": "0",
+                "reward_per_session": U128(RPS),
+                "session_interval": "1"
+            },
+            "ft_contract_id": nft_contract.account_id(),
+            "stake_unit": U128(1000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert_failure(outcome, FarmingError::RewardTokenNotAllowed);
+}
+
+#[test]
+pub fn test_unpaid_reward() {
+    let (root, _artist, collector, ft_contract, nft_contract, farming_contract) = init();
+    let farmer = root.create_user("farmer".to_string(), to_yocto("10"));
+
+    // registered without storage balance and not with the reward token
+    farmer.call(
+        farming_contract.account_id(), 
+        "storage_deposit", 
+        &json!({
+            "registration_only": true
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        to_yocto("0.1")
+    ).assert_success();
+    collector.call(
+        nft_contract.account_id(), 
+        "nft_transfer", 
+        &json!({
+            "receiver_id": farmer.account_id(),
+            "token_id": NFT_ID_2
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    farmer.call(
+        nft_contract.account_id(), 
+        "nft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "token_id": NFT_ID_2,
+            "msg": &json!({"farm_id": "0"}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+
+    farmer.call(
+        farming_contract.account_id(), 
+        "claim_reward_by_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    let unpaid: Vec<(String, U128)> = root.view(
+        farming_contract.account_id(), 
+        "get_unpaid_rewards", 
+        &json!({
+            "account_id": farmer.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(unpaid.len(), 1);
+    assert_eq!(unpaid[0].0, ft_contract.account_id());
+    assert!(unpaid[0].1.0 > 0);
+
+    // paid once the farmer is registered with the reward token
+    farmer.call(
+        ft_contract.account_id(), 
+        "storage_deposit", 
+        &json!({}).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        to_yocto("0.00125")
+    ).assert_success();
+    farmer.call(
+        farming_contract.account_id(), 
+        "claim_unpaid_reward", 
+        &json!({
+            "seed_id": ft_contract.account_id()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    let balance: U128 = root.view(
+        ft_contract.account_id(), 
+        "ft_balance_of", 
+        &json!({
+            "account_id": farmer.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(balance, unpaid[0].1);
+
+    let outcome = farmer.call(
+        farming_contract.account_id(), 
+        "claim_unpaid_reward", 
+        &json!({
+            "seed_id": ft_contract.account_id()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert_failure(outcome, FarmingError::NothingToClaim);
+}
+
+#[test]
+pub fn test_storage_unregister() {
+    let (root, _artist, collector, _ft_contract, _nft_contract, farming_contract) = init();
+
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "storage_unregister", 
+        &json!({}).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert_failure(outcome, FarmingError::FarmerNotEmpty);
+
+    collector.call(
+        farming_contract.account_id(), 
+        "withdraw", 
+        &json!({
+            "farm_id": "0",
+            "token_id": NFT_ID_1
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "storage_unregister", 
+        &json!({}).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert!(outcome.unwrap_json::<bool>());
+    let farmer_info: Option<FarmerInfo> = root.view(
+        farming_contract.account_id(), 
+        "get_farmer", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert!(farmer_info.is_none());
+}
+
+#[test]
+pub fn test_ft_transfer_actions() {
+    let (root, artist, _collector, ft_contract, _nft_contract, farming_contract) = init();
+
+    artist.call(
+        ft_contract.account_id(), 
+        "ft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "amount": U128(1000),
+            "msg": &json!({"action": "fund_farm", "farm_id": "0"}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farm_info.total_reward.0, 100000000001000);
+
+    let balance_before: U128 = root.view(
+        ft_contract.account_id(), 
+        "ft_balance_of", 
+        &json!({
+            "account_id": artist.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+
+    // malformed msg is refunded instead of failing
+    artist.call(
+        ft_contract.account_id(), 
+        "ft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "amount": U128(1000),
+            "msg": "farm 0"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+
+    let balance_after: U128 = root.view(
+        ft_contract.account_id(), 
+        "ft_balance_of", 
+        &json!({
+            "account_id": artist.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(balance_before, balance_after);
+}
+
+#[test]
+pub fn test_third_party_funding() {
+    let (root, artist, collector, ft_contract, _nft_contract, farming_contract) = init();
+
+    artist.call(
+        ft_contract.account_id(), 
+        "ft_transfer", 
+        &json!({
+            "receiver_id": collector.account_id(),
+            "amount": U128(1000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+
+    artist.call(
+        farming_contract.account_id(), 
+        "set_funding_policy", 
+        &json!({
+            "farm_id": "0",
+            "funding_policy": "Anyone"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+
+    collector.call(
+        ft_contract.account_id(), 
+        "ft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "amount": U128(1000),
+            "msg": &json!({"action": "fund_farm", "farm_id": "0"}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+
+    let contribution: U128 = root.view(
+        farming_contract.account_id(), 
+        "get_farm_contribution", 
+        &json!({
+            "farm_id": "0",
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(contribution.0, 1000);
+
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farm_info.total_contributed.0, 100000000001000);
+}
+
+#[test]
+pub fn test_close_farm() {
+    let (root, artist, _collector, ft_contract, _nft_contract, farming_contract) = init();
+
+    // a farm with stake stops its reward and ends after the notice
+    artist.call(
+        farming_contract.account_id(), 
+        "close_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farm_info.farm_status, "Running");
+    assert!(farm_info.closing_at.is_some());
+    assert_eq!(farm_info.reward_per_session.0, 0);
+
+    let outcome = artist.call(
+        farming_contract.account_id(), 
+        "close_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::FarmClosing);
+
+    // a farm without stake ends at once and refunds its funder
+    artist.call(
+        farming_contract.account_id(), 
+        "create_ft_farm", 
+        &json!({
+            "terms": {
+                "seed_id": ft_contract.account_id(),
+                "start_at": "0",
+                "reward_per_session": U128(RPS),
+                "session_interval": "1"
+            },
+            "ft_contract_id": ft_contract.account_id(),
+            "stake_unit": U128(1000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    artist.call(
+        ft_contract.account_id(), 
+        "ft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "amount": U128(1000),
+            "msg": &json!({"farm_id": "1"}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    artist.call(
+        farming_contract.account_id(), 
+        "close_farm", 
+        &json!({
+            "farm_id": "1"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "1"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farm_info.farm_status, "Ended");
+    assert_eq!(farm_info.refundable.0, 1000);
+
+    let balance_before: U128 = root.view(
+        ft_contract.account_id(), 
+        "ft_balance_of", 
+        &json!({
+            "account_id": artist.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    artist.call(
+        farming_contract.account_id(), 
+        "claim_refund", 
+        &json!({
+            "farm_id": "1"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    let balance_after: U128 = root.view(
+        ft_contract.account_id(), 
+        "ft_balance_of", 
+        &json!({
+            "account_id": artist.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(balance_after.0 - balance_before.0, 1000);
+
+    let outcome = artist.call(
+        farming_contract.account_id(), 
+        "claim_refund", 
+        &json!({
+            "farm_id": "1"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert_failure(outcome, FarmingError::NothingToRefund);
+}
+
+#[test]
+pub fn test_farm_stats() {
+    let (root, artist, collector, ft_contract, nft_contract, farming_contract) = init();
+
+    root.call(
+        farming_contract.account_id(), 
+        "set_nft_boost", 
+        &json!({
+            "nft_contract_id": nft_contract.account_id(),
+            "boost_bps": 5000
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+    artist.call(
+        farming_contract.account_id(), 
+        "create_hybrid_farm", 
+        &json!({
+            "terms": {
+                "seed_id": ft_contract.account_id(),
+                "start_at": "0",
+                "reward_per_session": U128(RPS),
+                "session_interval": "1"
+            },
+            "ft_contract_id": ft_contract.account_id(),
+            "stake_unit": U128(1000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    artist.call(
+        ft_contract.account_id(), 
+        "ft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "amount": U128(1000000000000000),
+            "msg": &json!({"farm_id": "1"}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    artist.call(
+        ft_contract.account_id(), 
+        "ft_transfer", 
+        &json!({
+            "receiver_id": collector.account_id(),
+            "amount": U128(100000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    collector.call(
+        ft_contract.account_id(), 
+        "ft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "amount": U128(100000),
+            "msg": &json!({"farm_id": "1", "stake": true}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    collector.call(
+        nft_contract.account_id(), 
+        "nft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "token_id": NFT_ID_2,
+            "msg": &json!({"farm_id": "1"}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+
+    // 100 units of stake with a boost of 50%
+    let stats: Option<FarmStats> = root.view(
+        farming_contract.account_id(), 
+        "get_farm_stats", 
+        &json!({
+            "farm_id": "1"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    let stats = stats.unwrap();
+    assert_eq!(stats.total_weight.0, 150);
+    assert_eq!(stats.staked_nfts, 1);
+    assert_eq!(stats.emission_per_second.0, RPS * 150 * 1_000_000_000);
+    assert!(stats.depleted_at.is_some());
+    assert_eq!(stats.distributed_bps, 0);
+
+    let stats: Option<FarmStats> = root.view(
+        farming_contract.account_id(), 
+        "get_farm_stats", 
+        &json!({
+            "farm_id": "99"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert!(stats.is_none());
+}
+
+#[test]
+pub fn test_reward_stream() {
+    let (root, artist, _collector, ft_contract, _nft_contract, farming_contract) = init();
+
+    // a stream can not end before it starts
+    let outcome = artist.call(
+        ft_contract.account_id(), 
+        "ft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "amount": U128(1000),
+            "msg": &json!({
+                "action": "add_stream",
+                "farm_id": "0",
+                "start_at": "4000000000000000000",
+                "end_at": "3000000000000000000"
+            }).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert!(outcome
+        .promise_results()
+        .iter()
+        .flatten()
+        .any(|result| result
+            .logs()
+            .iter()
+            .any(|log| log.contains(FarmingError::InvalidStream.code()))));
+
+    artist.call(
+        ft_contract.account_id(), 
+        "ft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "amount": U128(1000),
+            "msg": &json!({
+                "action": "add_stream",
+                "farm_id": "0",
+                "start_at": "4000000000000000000",
+                "end_at": "4000000001000000000"
+            }).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    // the stream is funded apart from the session reward
+    assert_eq!(farm_info.total_reward.0, 100000000000000);
+    assert_eq!(farm_info.stream_reward.0, 1000);
+    assert_eq!(
+        farm_info.streams,
+        vec![(4000000000000000000, 4000000001000000000, U128(1000))]
+    );
+}
+
+#[test]
+pub fn test_reward_vesting() {
+    let (root, artist, collector, ft_contract, _nft_contract, farming_contract) = init();
+
+    let outcome = artist.call(
+        farming_contract.account_id(), 
+        "set_farm_vesting", 
+        &json!({
+            "farm_id": "0",
+            "vesting": {"share_bps": 20000, "days": 30}
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::InvalidVesting);
+
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "set_farm_vesting", 
+        &json!({
+            "farm_id": "0",
+            "vesting": {"share_bps": 5000, "days": 30}
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::NotFarmOwner);
+
+    artist.call(
+        farming_contract.account_id(), 
+        "set_farm_vesting", 
+        &json!({
+            "farm_id": "0",
+            "vesting": {"share_bps": 5000, "days": 30}
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farm_info.vesting, Some(VestingTerms { share_bps: 5000, days: 30 }));
+
+    let balances: Vec<VestingBalance> = root.view(
+        farming_contract.account_id(), 
+        "get_vesting_balances", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert!(balances.is_empty());
+
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "claim_vested", 
+        &json!({
+            "seed_id": ft_contract.account_id()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert_failure(outcome, FarmingError::NothingToClaim);
+
+    let balance_before: U128 = root.view(
+        ft_contract.account_id(), 
+        "ft_balance_of", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    collector.call(
+        farming_contract.account_id(), 
+        "claim_reward_by_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    let balance_after: U128 = root.view(
+        ft_contract.account_id(), 
+        "ft_balance_of", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+
+    let balances: Vec<VestingBalance> = root.view(
+        farming_contract.account_id(), 
+        "get_vesting_balances", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(balances.len(), 1);
+    assert!(balances[0].locked.0 + balances[0].unlocked.0 > 0);
+
+    // only the part paid at once counts as claimed
+    let seed_info: Option<SeedInfo> = root.view(
+        farming_contract.account_id(), 
+        "get_seed", 
+        &json!({
+            "seed_id": ft_contract.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(seed_info.unwrap().total_claimed.0, balance_after.0 - balance_before.0);
+}
+
+#[test]
+pub fn test_loyalty_curve() {
+    let (root, artist, collector, _ft_contract, _nft_contract, farming_contract) = init();
+
+    let outcome = artist.call(
+        farming_contract.account_id(), 
+        "set_loyalty_curve", 
+        &json!({
+            "farm_id": "0",
+            "loyalty_curve": [{"days": 90, "bonus_bps": 2500}, {"days": 30, "bonus_bps": 1000}]
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::InvalidLoyaltyCurve);
+
+    artist.call(
+        farming_contract.account_id(), 
+        "set_loyalty_curve", 
+        &json!({
+            "farm_id": "0",
+            "loyalty_curve": [{"days": 30, "bonus_bps": 1000}, {"days": 90, "bonus_bps": 2500}]
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(
+        farm_info.loyalty_curve,
+        vec![
+            LoyaltyTier { days: 30, bonus_bps: 1000 },
+            LoyaltyTier { days: 90, bonus_bps: 2500 }
+        ]
+    );
+
+    // the NFT staked in init is far from the first tier
+    let portfolio: Option<Vec<FarmPosition>> = root.view(
+        farming_contract.account_id(), 
+        "get_farmer_portfolio", 
+        &json!({
+            "account_id": collector.account_id(),
+            "from_index": 0,
+            "limit": 10
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    let positions = portfolio.unwrap();
+    assert_eq!(positions[0].tokens[0].multiplier_bps, 10000);
+
+    let outcome = artist.call(
+        farming_contract.account_id(), 
+        "set_loyalty_curve", 
+        &json!({
+            "farm_id": "0",
+            "loyalty_curve": [{"days": 0, "bonus_bps": 20000}]
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::InvalidLoyaltyCurve);
+
+    // removing the curve keeps the bonus accrued until then
+    artist.call(
+        farming_contract.account_id(), 
+        "set_loyalty_curve", 
+        &json!({
+            "farm_id": "0",
+            "loyalty_curve": [{"days": 0, "bonus_bps": 10000}]
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+    let claimable_with_bonus: Option<U128> = root.view(
+        farming_contract.account_id(), 
+        "get_claimable_amount", 
+        &json!({
+            "farm_id": "0",
+            "farmer_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    artist.call(
+        farming_contract.account_id(), 
+        "set_loyalty_curve", 
+        &json!({
+            "farm_id": "0",
+            "loyalty_curve": []
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+    let claimable: Option<U128> = root.view(
+        farming_contract.account_id(), 
+        "get_claimable_amount", 
+        &json!({
+            "farm_id": "0",
+            "farmer_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert!(claimable.unwrap().0 >= claimable_with_bonus.unwrap().0);
+}
+
+#[test]
+pub fn test_nft_sets() {
+    let (root, artist, collector, _ft_contract, _nft_contract, farming_contract) = init();
+
+    let outcome = artist.call(
+        farming_contract.account_id(), 
+        "set_nft_sets", 
+        &json!({
+            "farm_id": "0",
+            "nft_sets": [
+                {"name": "tokens", "rule": {"AllWithPrefix": "token-"}, "bonus_bps": 1000},
+                {"name": "tokens", "rule": {"OneOfEach": ["token-1", "token-2"]}, "bonus_bps": 500}
+            ]
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::InvalidNftSets);
+
+    artist.call(
+        farming_contract.account_id(), 
+        "set_nft_sets", 
+        &json!({
+            "farm_id": "0",
+            "nft_sets": [
+                {"name": "tokens", "rule": {"AllWithPrefix": "token-"}, "bonus_bps": 1000},
+                {"name": "pair", "rule": {"OneOfEach": ["token-1", "token-2"]}, "bonus_bps": 500}
+            ]
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(
+        farm_info.nft_sets,
+        vec![
+            NftSet {
+                name: "tokens".to_string(),
+                rule: SetRule::AllWithPrefix("token-".to_string()),
+                bonus_bps: 1000
+            },
+            NftSet {
+                name: "pair".to_string(),
+                rule: SetRule::OneOfEach(vec!["token-1".to_string(), "token-2".to_string()]),
+                bonus_bps: 500
+            }
+        ]
+    );
+
+    // token-1 alone does not complete the sets
+    assert!(root.borrow_runtime_mut().produce_blocks(10).is_ok());
+    collector.call(
+        farming_contract.account_id(), 
+        "claim_reward_by_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    let farmer_info: FarmerInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farmer", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert!(farmer_info.staking_info[0].completed_sets.is_empty());
+
+    // it does once the other tokens are not accepted anymore
+    artist.call(
+        farming_contract.account_id(), 
+        "remove_accepted_nfts", 
+        &json!({
+            "farm_id": "0",
+            "tokens": ["token-2", "token-3"]
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+    assert!(root.borrow_runtime_mut().produce_blocks(10).is_ok());
+    collector.call(
+        farming_contract.account_id(), 
+        "claim_reward_by_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    let farmer_info: FarmerInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farmer", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farmer_info.staking_info[0].completed_sets, vec!["tokens".to_string()]);
+    assert_eq!(farmer_info.staking_info[0].set_bonus_bps, 1000);
+}
+
+#[test]
+pub fn test_farm_capacity() {
+    let (root, artist, collector, ft_contract, nft_contract, farming_contract) = init();
+
+    artist.call(
+        farming_contract.account_id(), 
+        "create_farm", 
+        &json!({
+            "terms": {
+                "seed_id": ft_contract.account_id(),
+                "start_at": "0",
+                "reward_per_session": U128(RPS),
+                "session_interval": "1",
+                "max_total_staked": "1",
+                "max_per_wallet": "1"
+            },
+            "nft_contract_id": nft_contract.account_id(),
+            "accepted_nfts": ["token-2", "token-3"]
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+
+    collector.call(
+        nft_contract.account_id(), 
+        "nft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "token_id": NFT_ID_2,
+            "msg": &json!({"farm_id": "1"}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "1"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farm_info.staked_ids, vec![NFT_ID_2.to_string()]);
+    assert_eq!(farm_info.max_per_wallet, Some(1));
+    assert_eq!(farm_info.remaining_capacity, Some(0));
+
+    // a full farm gives the NFT back instead of failing
+    let outcome = collector.call(
+        nft_contract.account_id(), 
+        "nft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "token_id": NFT_ID_3,
+            "msg": &json!({"farm_id": "1"}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert!(outcome
+        .promise_results()
+        .iter()
+        .flatten()
+        .any(|result| result
+            .logs()
+            .iter()
+            .any(|log| log.contains(FarmingError::FarmFull.code()))));
+
+    let token: serde_json::Value = root.view(
+        nft_contract.account_id(), 
+        "nft_token", 
+        &json!({
+            "token_id": NFT_ID_3
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(token["owner_id"], collector.account_id());
+}
+
+#[test]
+pub fn test_hybrid_boost() {
+    let (root, artist, collector, ft_contract, nft_contract, farming_contract) = init();
+
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "set_nft_boost", 
+        &json!({
+            "nft_contract_id": nft_contract.account_id(),
+            "boost_bps": 5000
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::NotAllowed);
+
+    root.call(
+        farming_contract.account_id(), 
+        "set_nft_boost", 
+        &json!({
+            "nft_contract_id": nft_contract.account_id(),
+            "boost_bps": 5000
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+    let boost_bps: u32 = root.view(
+        farming_contract.account_id(), 
+        "get_nft_boost", 
+        &json!({
+            "nft_contract_id": nft_contract.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(boost_bps, 5000);
+
+    artist.call(
+        farming_contract.account_id(), 
+        "create_hybrid_farm", 
+        &json!({
+            "terms": {
+                "seed_id": ft_contract.account_id(),
+                "start_at": "0",
+                "reward_per_session": U128(RPS),
+                "session_interval": "1"
+            },
+            "ft_contract_id": ft_contract.account_id(),
+            "stake_unit": U128(1000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    artist.call(
+        ft_contract.account_id(), 
+        "ft_transfer", 
+        &json!({
+            "receiver_id": collector.account_id(),
+            "amount": U128(100000)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    collector.call(
+        ft_contract.account_id(), 
+        "ft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "amount": U128(100000),
+            "msg": &json!({"farm_id": "1", "stake": true}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    collector.call(
+        nft_contract.account_id(), 
+        "nft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "token_id": NFT_ID_2,
+            "msg": &json!({"farm_id": "1"}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+
+    let farmer_info: Option<FarmerInfo> = root.view(
+        farming_contract.account_id(), 
+        "get_farmer", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    let farmer_info = farmer_info.unwrap();
+    let index = farmer_info.farm_staking.iter().position(|farm_id| farm_id == "1").unwrap();
+    assert_eq!(farmer_info.farm_kinds[index], "Hybrid");
+    assert_eq!(farmer_info.staking_info[index].amount, 100000);
+    assert_eq!(farmer_info.staking_info[index].boost_bps, 5000);
+
+    // one boosting NFT per farmer
+    let outcome = collector.call(
+        nft_contract.account_id(), 
+        "nft_transfer_call", 
+        &json!({
+            "receiver_id": farming_contract.account_id(),
+            "token_id": NFT_ID_3,
+            "msg": &json!({"farm_id": "1"}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert!(outcome
+        .promise_results()
+        .iter()
+        .flatten()
+        .any(|result| format!("{:?}", result.status()).contains(FarmingError::AlreadyBoosted.code())));
+
+    let token: serde_json::Value = root.view(
+        nft_contract.account_id(), 
+        "nft_token", 
+        &json!({
+            "token_id": NFT_ID_3
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(token["owner_id"], collector.account_id());
+}
+
+#[test]
+pub fn test_mt_farm() {
+    let (root, artist, collector, ft_contract, _nft_contract, farming_contract) = init();
+    let mt_contract = root.create_user("mt_contract".to_string(), to_yocto("10"));
+
+    let outcome = artist.call(
+        farming_contract.account_id(), 
+        "create_mt_farm", 
+        &json!({
+            "terms": {
+                "seed_id": ft_contract.account_id(),
+                "start_at": "0",
+                "reward_per_session": U128(RPS),
+                "session_interval": "1"
+            },
+            "mt_contract_id": mt_contract.account_id(),
+            "accepted_tokens": []
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert_failure(outcome, FarmingError::NoAcceptedTokens);
+
+    artist.call(
+        farming_contract.account_id(), 
+        "create_mt_farm", 
+        &json!({
+            "terms": {
+                "seed_id": ft_contract.account_id(),
+                "start_at": "0",
+                "reward_per_session": U128(RPS),
+                "session_interval": "1"
+            },
+            "mt_contract_id": mt_contract.account_id(),
+            "accepted_tokens": ["gold"]
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "1"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farm_info.farm_kind, "MultiToken");
+    assert_eq!(farm_info.mt_contract_id, Some(mt_contract.account_id()));
+
+    // the multi token contract calls back with the balances it moved
+    let outcome = mt_contract.call(
+        farming_contract.account_id(), 
+        "mt_on_transfer", 
+        &json!({
+            "sender_id": collector.account_id(),
+            "previous_owner_ids": [collector.account_id()],
+            "token_ids": ["gold"],
+            "amounts": [U128(10)],
+            "msg": &json!({"farm_id": "1"}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_eq!(outcome.unwrap_json::<Vec<U128>>(), vec![U128(0)]);
+
+    let farmer_info: Option<FarmerInfo> = root.view(
+        farming_contract.account_id(), 
+        "get_farmer", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    let farmer_info = farmer_info.unwrap();
+    let index = farmer_info.farm_staking.iter().position(|farm_id| farm_id == "1").unwrap();
+    assert_eq!(farmer_info.farm_kinds[index], "MultiToken");
+    assert_eq!(farmer_info.staking_info[index].amount, 10);
+    assert_eq!(farmer_info.staking_info[index].token_balances.get("gold"), Some(&10));
+
+    let outcome = mt_contract.call(
+        farming_contract.account_id(), 
+        "mt_on_transfer", 
+        &json!({
+            "sender_id": collector.account_id(),
+            "previous_owner_ids": [collector.account_id()],
+            "token_ids": ["silver"],
+            "amounts": [U128(10)],
+            "msg": &json!({"farm_id": "1"}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::TokenNotAccepted);
+
+    // tokens of another contract are not accepted
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "mt_on_transfer", 
+        &json!({
+            "sender_id": collector.account_id(),
+            "previous_owner_ids": [collector.account_id()],
+            "token_ids": ["gold"],
+            "amounts": [U128(10)],
+            "msg": &json!({"farm_id": "1"}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::TokenNotAccepted);
+
+    // the test multi token contract has no code, so the transfer back fails
+    // and the balances are staked again
+    collector.call(
+        farming_contract.account_id(), 
+        "unstake_mt", 
+        &json!({
+            "farm_id": "1",
+            "token_ids": ["gold"],
+            "amounts": [U128(4)]
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    let farmer_info: Option<FarmerInfo> = root.view(
+        farming_contract.account_id(), 
+        "get_farmer", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    let farmer_info = farmer_info.unwrap();
+    assert_eq!(farmer_info.staking_info[index].amount, 10);
+    assert_eq!(farmer_info.staking_info[index].token_balances.get("gold"), Some(&10));
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "1"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farm_info.total_staked.0, 10);
+}
+
+#[test]
+pub fn test_approval_staking() {
+    let (root, _artist, collector, _ft_contract, nft_contract, farming_contract) = init();
+
+    collector.call(
+        nft_contract.account_id(), 
+        "nft_approve", 
+        &json!({
+            "token_id": NFT_ID_2,
+            "account_id": farming_contract.account_id(),
+            "msg": &json!({"farm_id": "0"}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        to_yocto("0.01")
+    ).assert_success();
+
+    // the NFT stays with its owner
+    let token: serde_json::Value = root.view(
+        nft_contract.account_id(), 
+        "nft_token", 
+        &json!({
+            "token_id": NFT_ID_2
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(token["owner_id"], collector.account_id());
+
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    let index = farm_info.staked_ids.iter().position(|token_id| token_id == NFT_ID_2).unwrap();
+    assert_eq!(farm_info.staked_nfts[index].owner_id, collector.account_id());
+    assert!(farm_info.staked_nfts[index].approval_id.is_some());
+
+    let farmer_info: Option<FarmerInfo> = root.view(
+        farming_contract.account_id(), 
+        "get_farmer", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    let farmer_info = farmer_info.unwrap();
+    let index = farmer_info.farm_staking.iter().position(|farm_id| farm_id == "0").unwrap();
+    assert_eq!(farmer_info.staking_info[index].amount, 2);
+
+    // an approved NFT is staked once
+    let outcome = collector.call(
+        nft_contract.account_id(), 
+        "nft_approve", 
+        &json!({
+            "token_id": NFT_ID_2,
+            "account_id": farming_contract.account_id(),
+            "msg": &json!({"farm_id": "0"}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        to_yocto("0.01")
+    );
+    assert!(outcome
+        .promise_results()
+        .iter()
+        .flatten()
+        .any(|result| format!("{:?}", result.status()).contains(FarmingError::AlreadyStaked.code())));
+
+    // a revoked approval lets anyone evict the NFT, its owner forfeits the reward
+    collector.call(
+        nft_contract.account_id(), 
+        "nft_revoke", 
+        &json!({
+            "token_id": NFT_ID_2,
+            "account_id": farming_contract.account_id()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    ).assert_success();
+    root.call(
+        farming_contract.account_id(), 
+        "verify_approved_nfts", 
+        &json!({
+            "farm_id": "0",
+            "token_ids": [NFT_ID_2]
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert!(!farm_info.staked_ids.iter().any(|token_id| token_id == NFT_ID_2));
+    let farmer_info: Option<FarmerInfo> = root.view(
+        farming_contract.account_id(), 
+        "get_farmer", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    let farmer_info = farmer_info.unwrap();
+    assert_eq!(farmer_info.staking_info[index].amount, 1);
+    assert_eq!(farmer_info.staking_info[index].pending_reward, 0);
+
+    // the NFT can be staked again
+    collector.call(
+        nft_contract.account_id(), 
+        "nft_approve", 
+        &json!({
+            "token_id": NFT_ID_2,
+            "account_id": farming_contract.account_id(),
+            "msg": &json!({"farm_id": "0"}).to_string()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        to_yocto("0.01")
+    ).assert_success();
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert!(farm_info.staked_ids.iter().any(|token_id| token_id == NFT_ID_2));
+}
+
+#[test]
+pub fn test_creator_modes() {
+    let (root, artist, collector, ft_contract, nft_contract, farming_contract) = init();
+
+    let outcome = artist.call(
+        farming_contract.account_id(), 
+        "set_creator_mode", 
+        &json!({
+            "nft_contract_id": nft_contract.account_id(),
+            "creator_mode": "Admins"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::NotAllowed);
+
+    root.call(
+        farming_contract.account_id(), 
+        "set_creator_mode", 
+        &json!({
+            "nft_contract_id": nft_contract.account_id(),
+            "creator_mode": "Admins"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+    let creator_mode: CreatorMode = root.view(
+        farming_contract.account_id(), 
+        "get_creator_mode", 
+        &json!({
+            "nft_contract_id": nft_contract.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(creator_mode, CreatorMode::Admins);
+
+    let create_farm_args = json!({
+        "terms": {
+            "seed_id": ft_contract.account_id(),
+            "start_at": "0",
+            "reward_per_session": U128(RPS),
+            "session_interval": "1"
+        },
+        "nft_contract_id": nft_contract.account_id(),
+        "accepted_nfts": ["token-2"]
+    }).to_string();
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "create_farm", 
+        create_farm_args.as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert_failure(outcome, FarmingError::NotNftContractAdmin);
+
+    root.call(
+        farming_contract.account_id(), 
+        "add_nft_contract_admin", 
+        &json!({
+            "nft_contract_id": nft_contract.account_id(),
+            "account_id": collector.account_id()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+    let admins: Vec<String> = root.view(
+        farming_contract.account_id(), 
+        "get_nft_contract_admins", 
+        &json!({
+            "nft_contract_id": nft_contract.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(admins, vec![collector.account_id()]);
+
+    // admins create farms without owning the NFTs
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "create_farm", 
+        create_farm_args.as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert_eq!(outcome.unwrap_json::<String>(), "1");
+
+    root.call(
+        farming_contract.account_id(), 
+        "remove_nft_contract_admin", 
+        &json!({
+            "nft_contract_id": nft_contract.account_id(),
+            "account_id": collector.account_id()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "create_farm", 
+        create_farm_args.as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert_failure(outcome, FarmingError::NotNftContractAdmin);
+
+    root.call(
+        farming_contract.account_id(), 
+        "set_creator_mode", 
+        &json!({
+            "nft_contract_id": nft_contract.account_id(),
+            "creator_mode": "Permissionless"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "create_farm", 
+        create_farm_args.as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert_eq!(outcome.unwrap_json::<String>(), "2");
+}
+
+#[test]
+pub fn test_nft_contract_registry() {
+    let (root, artist, collector, ft_contract, nft_contract, farming_contract) = init();
+
+    let config = json!({
+        "name": "Collection",
+        "creator_mode": "CreatorOfNfts",
+        "farm_creators": [],
+        "max_farms": 1,
+        "boost_bps": 0,
+        "enabled": true
+    });
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "set_nft_contract", 
+        &json!({
+            "nft_contract_id": nft_contract.account_id(),
+            "config": config
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::NotAllowed);
+
+    root.call(
+        farming_contract.account_id(), 
+        "set_nft_contract", 
+        &json!({
+            "nft_contract_id": nft_contract.account_id(),
+            "config": config
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+
+    // the farm of init is still counted
+    let config: Option<NftContractConfig> = root.view(
+        farming_contract.account_id(), 
+        "get_nft_contract", 
+        &json!({
+            "nft_contract_id": nft_contract.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    let config = config.unwrap();
+    assert_eq!(config.name, "Collection");
+    assert_eq!(config.max_farms, Some(1));
+    assert_eq!(config.farm_count, 1);
+
+    let nft_contracts: Vec<(String, NftContractConfig)> = root.view(
+        farming_contract.account_id(), 
+        "list_nft_contracts", 
+        &json!({
+            "from_index": 0,
+            "limit": 10
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(nft_contracts.len(), 1);
+    assert_eq!(nft_contracts[0].0, nft_contract.account_id());
+
+    let outcome = artist.call(
+        farming_contract.account_id(), 
+        "create_farm", 
+        &json!({
+            "terms": {
+                "seed_id": ft_contract.account_id(),
+                "start_at": "0",
+                "reward_per_session": U128(RPS),
+                "session_interval": "1"
+            },
+            "nft_contract_id": nft_contract.account_id(),
+            "accepted_nfts": ["token-2"]
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert_failure(outcome, FarmingError::TooManyFarms);
+
+    let outcome = root.call(
+        farming_contract.account_id(), 
+        "remove_nft_contract", 
+        &json!({
+            "nft_contract_id": nft_contract.account_id()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert!(outcome.unwrap_json::<bool>());
+    let is_whitelisted: bool = root.view(
+        farming_contract.account_id(), 
+        "is_whitelist_nft_contract", 
+        &json!({
+            "nft_contract_id": nft_contract.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert!(!is_whitelisted);
+    let nft_contracts: Vec<(String, NftContractConfig)> = root.view(
+        farming_contract.account_id(), 
+        "list_nft_contracts", 
+        &json!({
+            "from_index": 0,
+            "limit": 10
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert!(nft_contracts.is_empty());
+}
+
+#[test]
+pub fn test_roles() {
+    let (root, _artist, collector, _ft_contract, nft_contract, farming_contract) = init();
+
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "grant_role", 
+        &json!({
+            "account_id": collector.account_id(),
+            "role": "Guardian"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::NotAllowed);
+
+    let outcome = root.call(
+        farming_contract.account_id(), 
+        "grant_role", 
+        &json!({
+            "account_id": collector.account_id(),
+            "role": "Guardian"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    outcome.assert_success();
+    assert!(outcome.logs()[0].contains("role_granted"));
+    let roles: Vec<Role> = root.view(
+        farming_contract.account_id(), 
+        "get_roles", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(roles, vec![Role::Guardian]);
+
+    // a guardian pauses the contract but does not manage the registry
+    collector.call(
+        farming_contract.account_id(), 
+        "set_running_state", 
+        &json!({
+            "state": "Paused"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+    let state: RunningState = root.view(
+        farming_contract.account_id(), 
+        "get_running_state", 
+        &json!({}).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(state, RunningState::Paused);
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "whitelist_nft_contract", 
+        &json!({
+            "nft_contract_id": nft_contract.account_id()
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    assert_failure(outcome, FarmingError::NotAllowed);
+
+    let outcome = root.call(
+        farming_contract.account_id(), 
+        "revoke_role", 
+        &json!({
+            "account_id": collector.account_id(),
+            "role": "Guardian"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    outcome.assert_success();
+    assert!(outcome.logs()[0].contains("role_revoked"));
+    let has_role: bool = root.view(
+        farming_contract.account_id(), 
+        "has_role", 
+        &json!({
+            "account_id": collector.account_id(),
+            "role": "Guardian"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert!(!has_role);
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "set_running_state", 
+        &json!({
+            "state": "Running"
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::NotAllowed);
+}
+
+#[test]
+pub fn test_update_reward_rate() {
+    let (root, artist, collector, _ft_contract, _nft_contract, farming_contract) = init();
+
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "update_reward_rate", 
+        &json!({
+            "farm_id": "0",
+            "new_rate": U128(RPS * 2),
+            "effective_at": U64(0)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::NotFarmOwner);
+
+    let outcome = artist.call(
+        farming_contract.account_id(), 
+        "update_reward_rate", 
+        &json!({
+            "farm_id": "0",
+            "new_rate": U128(RPS * 2),
+            "effective_at": U64(1)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::RateInPast);
+
+    let effective_at: u64 = 9_000_000_000_000_000_000;
+    artist.call(
+        farming_contract.account_id(), 
+        "update_reward_rate", 
+        &json!({
+            "farm_id": "0",
+            "new_rate": U128(RPS * 2),
+            "effective_at": U64(effective_at)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    ).assert_success();
+    let outcome = artist.call(
+        farming_contract.account_id(), 
+        "update_reward_rate", 
+        &json!({
+            "farm_id": "0",
+            "new_rate": U128(RPS * 3),
+            "effective_at": U64(effective_at - 1)
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        0
+    );
+    assert_failure(outcome, FarmingError::RateOutOfOrder);
+
+    // the current rate is unchanged until the new one is effective
+    let farm_info: FarmInfo = root.view(
+        farming_contract.account_id(), 
+        "get_farm", 
+        &json!({
+            "farm_id": "0"
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(farm_info.reward_per_session, U128(RPS));
+    assert_eq!(farm_info.rate_history, vec![(effective_at, U128(RPS * 2))]);
+}
+
+#[test]
+pub fn test_farmer_portfolio() {
+    let (root, _artist, collector, _ft_contract, _nft_contract, farming_contract) = init();
+
+    let portfolio: Option<Vec<FarmPosition>> = root.view(
+        farming_contract.account_id(), 
+        "get_farmer_portfolio", 
+        &json!({
+            "account_id": "unknown",
+            "from_index": 0,
+            "limit": 10
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert!(portfolio.is_none());
+
+    let portfolio: Option<Vec<FarmPosition>> = root.view(
+        farming_contract.account_id(), 
+        "get_farmer_portfolio", 
+        &json!({
+            "account_id": collector.account_id(),
+            "from_index": 0,
+            "limit": 10
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    let positions = portfolio.unwrap();
+    assert_eq!(positions.len(), 1);
+    assert_eq!(positions[0].farm_id, "0");
+    assert_eq!(positions[0].farm_kind, "NonFungible");
+    assert_eq!(positions[0].farm_status, "Running");
+    assert_eq!(positions[0].staked, U128(1));
+    assert_eq!(positions[0].tokens.len(), 1);
+    assert_eq!(positions[0].tokens[0].token_id, NFT_ID_1);
+    assert_eq!(positions[0].tokens[0].balance, U128(1));
+    assert_eq!(positions[0].tokens[0].claimable, positions[0].claimable);
+    assert!(!positions[0].tokens[0].locked);
+
+    let portfolio: Option<Vec<FarmPosition>> = root.view(
+        farming_contract.account_id(), 
+        "get_farmer_portfolio", 
+        &json!({
+            "account_id": collector.account_id(),
+            "from_index": 1,
+            "limit": 10
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert!(portfolio.unwrap().is_empty());
+}
+
+#[test]
+pub fn test_claim_all() {
+    let (root, _artist, collector, ft_contract, _nft_contract, farming_contract) = init();
+
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "claim_all", 
+        &json!({}).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    let result: ClaimAllResult = outcome.unwrap_json();
+    assert_eq!(result.next_index, None);
+    assert_eq!(result.claimed.len(), 1);
+    assert_eq!(result.claimed[0].0, ft_contract.account_id());
+    assert!(result.claimed[0].1 .0 > 0);
+
+    let balance: U128 = root.view(
+        ft_contract.account_id(), 
+        "ft_balance_of", 
+        &json!({
+            "account_id": collector.account_id()
+        }).to_string().as_bytes()
+    ).unwrap_json();
+    assert_eq!(balance, result.claimed[0].1);
+
+    // unknown farms are skipped
+    let outcome = collector.call(
+        farming_contract.account_id(), 
+        "claim_all", 
+        &json!({
+            "farm_ids": ["99", "unknown#0"]
+        }).to_string().as_bytes(),
+        DEFAULT_GAS, 
+        1
+    );
+    let result: ClaimAllResult = outcome.unwrap_json();
+    assert!(result.claimed.is_empty());
+    assert_eq!(result.next_index, None);
+}